@@ -0,0 +1,51 @@
+//! `cargo bench` from `trailsense-core`, deduplication of one upload window on the host.
+
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use std::hint::black_box;
+use trailsense_core::probes::{
+    counter::{bit_weights, deduplicate_probes, default_threshold},
+    fingerprint::Fingerprint,
+    probe_parser::FingerprintMethod,
+};
+
+/// Fingerprints the store holds at most per window.
+const WINDOW_LEN: usize = 2048;
+
+/// Xorshift, enough to spread fingerprints without a dependency.
+fn random(seed: &mut u64) -> u64 {
+    *seed ^= *seed << 13;
+    *seed ^= *seed >> 7;
+    *seed ^= *seed << 17;
+    *seed
+}
+
+/// `WINDOW_LEN` fingerprints drawn from a pool of `distinct` ones, few repeat like a quiet site, many like a busy one.
+fn window(distinct: usize) -> Vec<Fingerprint> {
+    let mut seed = 0x9e37_79b9_7f4a_7c15;
+    let pool: Vec<Fingerprint> = (0..distinct)
+        .map(|_| random(&mut seed) as Fingerprint)
+        .collect();
+    (0..WINDOW_LEN)
+        .map(|_| pool[random(&mut seed) as usize % distinct])
+        .collect()
+}
+
+fn deduplicate(c: &mut Criterion) {
+    let mut group = c.benchmark_group("deduplicate_probes");
+    for method in [FingerprintMethod::Masks, FingerprintMethod::Features] {
+        let weights = bit_weights(method);
+        let threshold = default_threshold(method);
+        for distinct in [64, WINDOW_LEN] {
+            let input = window(distinct);
+            group.bench_with_input(
+                BenchmarkId::new(format!("{:?}", method), distinct),
+                &input,
+                |b, input| b.iter(|| deduplicate_probes(black_box(input), &weights, threshold)),
+            );
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, deduplicate);
+criterion_main!(benches);
@@ -0,0 +1,107 @@
+extern crate alloc;
+use alloc::vec::Vec;
+
+use embassy_time::{Duration, Instant};
+
+/// Failed unlocks allowed before the backoff starts, leaves room for typos.
+pub const FREE_UNLOCK_ATTEMPTS: u32 = 3;
+pub const MAX_UNLOCK_BACKOFF: Duration = Duration::from_secs(5 * 60);
+
+/// Failed unlocks so far and when the next attempt is accepted.
+#[derive(Clone, Copy)]
+pub struct UnlockGuard {
+    pub failures: u32,
+    pub retry_at: Instant,
+}
+
+impl UnlockGuard {
+    pub const NEW: Self = Self {
+        failures: 0,
+        retry_at: Instant::MIN,
+    };
+
+    pub fn failed(self, now: Instant) -> Self {
+        let failures = self.failures.saturating_add(1);
+        Self {
+            failures,
+            retry_at: now + unlock_backoff(failures),
+        }
+    }
+}
+
+/// Wait after the given number of consecutive failed unlocks, doubling from one second up to the maximum.
+pub fn unlock_backoff(failures: u32) -> Duration {
+    match failures.checked_sub(FREE_UNLOCK_ATTEMPTS) {
+        None | Some(0) => Duration::from_ticks(0),
+        Some(excess) => Duration::from_secs(1u64 << (excess - 1).min(16)).min(MAX_UNLOCK_BACKOFF),
+    }
+}
+
+/// An empty password never matches, a node without one stays locked.
+pub fn password_matches(attempt: &[u8], password: &str) -> bool {
+    !password.is_empty() && attempt == password.as_bytes()
+}
+
+/// A long write arrives as chunks at increasing offsets, each one replaces everything from its offset on.
+pub fn write_at(buf: &mut Vec<u8>, offset: usize, data: &[u8]) {
+    buf.resize(offset, 0);
+    buf.extend_from_slice(data);
+}
+
+/// Serves `value` from `offset` on, long reads ask for the rest at increasing offsets.
+pub fn read_at(value: &[u8], offset: usize, data: &mut [u8]) -> usize {
+    let rest = value.get(offset..).unwrap_or_default();
+    let len = rest.len().min(data.len());
+    data[..len].copy_from_slice(&rest[..len]);
+    len
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_password_never_unlocks() {
+        assert!(!password_matches(b"", ""));
+        assert!(!password_matches(b"anything", ""));
+        assert!(password_matches(b"abcdefgh", "abcdefgh"));
+        assert!(!password_matches(b"abcdefg", "abcdefgh"));
+    }
+
+    #[test]
+    fn unlock_backoff_doubles_after_the_free_attempts_up_to_the_maximum() {
+        let backoff: Vec<u64> = (0..=14).map(|f| unlock_backoff(f).as_secs()).collect();
+        assert_eq!(
+            backoff,
+            [0, 0, 0, 0, 1, 2, 4, 8, 16, 32, 64, 128, 256, 300, 300]
+        );
+        assert_eq!(unlock_backoff(u32::MAX), MAX_UNLOCK_BACKOFF);
+    }
+
+    #[test]
+    fn failed_unlocks_push_the_next_attempt_out() {
+        let now = Instant::from_secs(100);
+        let mut guard = UnlockGuard::NEW;
+        for _ in 0..FREE_UNLOCK_ATTEMPTS {
+            guard = guard.failed(now);
+            assert_eq!(guard.retry_at, now);
+        }
+        guard = guard.failed(now);
+        assert_eq!(guard.retry_at, now + Duration::from_secs(1));
+    }
+
+    #[test]
+    fn long_writes_replace_from_their_offset() {
+        let mut buf = Vec::new();
+        write_at(&mut buf, 0, b"abcdef");
+        write_at(&mut buf, 4, b"XYZ");
+        assert_eq!(buf, b"abcdXYZ");
+        write_at(&mut buf, 0, b"q");
+        assert_eq!(buf, b"q");
+
+        let mut out = [0u8; 4];
+        assert_eq!(read_at(b"abcdef", 4, &mut out), 2);
+        assert_eq!(&out[..2], b"ef");
+        assert_eq!(read_at(b"abc", 9, &mut out), 0);
+    }
+}
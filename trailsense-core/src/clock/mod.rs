@@ -0,0 +1,39 @@
+use core::cell::Cell;
+use embassy_sync::blocking_mutex::{Mutex, raw::CriticalSectionRawMutex};
+use embassy_time::{Duration, Instant};
+
+/// Unix time in microseconds at `Instant` zero, `None` until the first successful time sync.
+static UNIX_OFFSET_US: Mutex<CriticalSectionRawMutex, Cell<Option<i64>>> =
+    Mutex::new(Cell::new(None));
+
+pub fn set_unix_offset_us(offset_us: i64) {
+    UNIX_OFFSET_US.lock(|o| o.set(Some(offset_us)));
+}
+
+pub fn is_synced() -> bool {
+    UNIX_OFFSET_US.lock(|o| o.get()).is_some()
+}
+
+/// Current UTC time as Unix microseconds, `None` while the clock is not synced.
+pub fn unix_now_us() -> Option<u64> {
+    unix_us_at(Instant::now())
+}
+
+pub fn unix_now_secs() -> Option<u64> {
+    unix_now_us().map(|us| us / 1_000_000)
+}
+
+pub fn unix_us_at(instant: Instant) -> Option<u64> {
+    let offset = UNIX_OFFSET_US.lock(|o| o.get())?;
+    u64::try_from(instant.as_micros() as i64 + offset).ok()
+}
+
+/// Time until the next multiple of `period` in UTC, so counting windows line up across nodes.
+/// Falls back to a full `period` while the clock is not synced.
+pub fn until_next_boundary(period: Duration) -> Duration {
+    let period_us = period.as_micros();
+    match unix_now_us() {
+        Some(now) if period_us > 0 => Duration::from_micros(period_us - now % period_us),
+        _ => period,
+    }
+}
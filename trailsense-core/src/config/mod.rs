@@ -0,0 +1,550 @@
+extern crate alloc;
+use alloc::{
+    format,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+use core::cell::RefCell;
+
+use embassy_sync::blocking_mutex::{Mutex, raw::CriticalSectionRawMutex};
+use embassy_time::Duration;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use crate::{
+    config::store::StoreError,
+    network::tls,
+    packages::downsample::EvictionConfig,
+    probes::{
+        counter,
+        filter::{self, FilterRule},
+        probe_parser::FingerprintMethod,
+    },
+};
+
+pub mod store;
+
+/// Schema of the stored config. Added fields only need a default, missing ones are filled in on load.
+/// A field that changes meaning bumps it, stored configs of another version are rejected.
+pub const CONFIG_VERSION: u16 = 1;
+
+// Build-time values only seed the defaults written on first boot, so a factory image can be flashed pre-provisioned.
+const WIFI_SSID: Option<&str> = option_env!("WIFI_SSID");
+const WIFI_PASSWORD: Option<&str> = option_env!("WIFI_PASSWORD");
+const PORTAL_PASSWORD: Option<&str> = option_env!("TRAILSENSE_PORTAL_PASSWORD");
+const API_URL: &str = match option_env!("TRAILSENSE_API_URL") {
+    Some(v) => v,
+    None => "https://api.trailsense.daugt.com",
+};
+const DEVICE_ID: &str = match option_env!("TRAILSENSE_EDGE_ID") {
+    Some(v) => v,
+    None => "71ec4873-944e-49c1-b7c4-4b856797715f",
+};
+const DEVICE_SECRET: Option<&str> = option_env!("TRAILSENSE_DEVICE_SECRET");
+const API_CA_PEM: Option<&str> = option_env!("TRAILSENSE_API_CA_PEM");
+const NTP_SERVER: &str = match option_env!("TRAILSENSE_NTP_SERVER") {
+    Some(v) => v,
+    None => "pool.ntp.org",
+};
+const GSM_APN: &str = match option_env!("TRAILSENSE_GSM_APN") {
+    Some(v) => v,
+    None => "internet",
+};
+
+/// Saved Wi-Fi networks, also the number of rows in the portal form.
+pub const MAX_WIFI_NETWORKS: usize = 4;
+
+/// 2.4 GHz channels 1 to 14, also the longest hop list.
+pub const MAX_HOP_CHANNELS: usize = 14;
+
+/// Each rule is checked per probe, so the list stays short.
+const MAX_FILTER_RULES: usize = 32;
+
+/// Longest URL that still fits the request URL buffers together with the `/ingest` path.
+const MAX_API_URL_LEN: usize = 112;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+pub struct WifiNetwork {
+    pub ssid: String,
+    pub password: String,
+}
+
+/// # Config
+///
+/// Everything that differs between nodes or deployments. Empty strings mean "not set".
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct Config {
+    pub version: u16,
+    /// Known networks, highest priority first.
+    pub wifi_networks: Vec<WifiNetwork>,
+    /// Consecutive failed connects to one network before the next known network is tried.
+    pub wifi_failover_after_failures: u8,
+    /// WPA2 password of the provisioning access point and BLE unlock. Left empty, every boot fills in the
+    /// per-device password from `device_password`, which is also what goes on the node's label.
+    /// Without either, the portal and BLE provisioning stay closed.
+    pub portal_password: String,
+    /// Consecutive failed Wi-Fi connects before the provisioning portal opens, 0 disables it.
+    pub portal_after_failures: u8,
+    pub portal_timeout_secs: u32,
+    pub api_url: String,
+    pub device_id: String,
+    pub device_secret: String,
+    pub api_ca_pem: String,
+    pub ntp_server: String,
+    pub gsm_apn: String,
+    pub upload_period_secs: u32,
+    pub send_timeout_secs: u32,
+    pub send_retry_delay_ms: u32,
+    pub send_attempts: u8,
+    pub dns_reconnect_threshold: u8,
+    pub dns_restart_threshold: u8,
+    pub drop_zero_count_packages: bool,
+    pub max_bucket_span_secs: u64,
+    /// Hop the sniffer across `hop_channels` instead of staying on the station's channel. Away from its
+    /// channel an associated station misses beacons and buffered frames until the access point drops it,
+    /// so the radio stays on that channel for the whole association. Hopping only covers the time the
+    /// station waits to retry a failed connect, e.g. a GSM node out of Wi-Fi range.
+    pub channel_hopping: bool,
+    pub hop_channels: Vec<u8>,
+    pub hop_dwell_ms: u32,
+    /// Transmitters excluded from (or, with OUI allow rules, admitted to) counting, e.g. site infrastructure.
+    pub mac_filter: Vec<FilterRule>,
+    pub fingerprint_method: FingerprintMethod,
+    /// Fingerprints within this weighted distance count as one device, see `counter::bit_weights`.
+    /// Unset, `counter::default_threshold` of the fingerprint method applies.
+    pub dedup_threshold: Option<f32>,
+    /// Probes weaker than this are not counted, keeps devices beyond the counting zone out.
+    pub rssi_min_dbm: Option<i8>,
+    /// Probes stronger than this are not counted, for a band that leaves out the node's surroundings.
+    pub rssi_max_dbm: Option<i8>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            version: CONFIG_VERSION,
+            wifi_networks: WIFI_SSID
+                .map(|ssid| WifiNetwork {
+                    ssid: ssid.to_string(),
+                    password: WIFI_PASSWORD.unwrap_or_default().to_string(),
+                })
+                .into_iter()
+                .collect(),
+            wifi_failover_after_failures: 3,
+            portal_password: PORTAL_PASSWORD.unwrap_or_default().to_string(),
+            portal_after_failures: 10,
+            portal_timeout_secs: 10 * 60,
+            api_url: API_URL.to_string(),
+            device_id: DEVICE_ID.to_string(),
+            device_secret: DEVICE_SECRET.unwrap_or_default().to_string(),
+            api_ca_pem: API_CA_PEM.unwrap_or_default().to_string(),
+            ntp_server: NTP_SERVER.to_string(),
+            gsm_apn: GSM_APN.to_string(),
+            upload_period_secs: 20,
+            send_timeout_secs: 30,
+            send_retry_delay_ms: 500,
+            send_attempts: 5,
+            dns_reconnect_threshold: 2,
+            dns_restart_threshold: 4,
+            drop_zero_count_packages: EvictionConfig::DEFAULT.drop_zero_counts,
+            max_bucket_span_secs: EvictionConfig::DEFAULT.max_bucket_span_secs,
+            channel_hopping: false,
+            hop_channels: (1..=13).collect(),
+            hop_dwell_ms: 250,
+            // Cisco and Espressif OUIs of our own test and gateway hardware.
+            mac_filter: ["54:8a:ba", "34:98:7a", "70:d3:79", "10:3c:59"]
+                .into_iter()
+                .map(FilterRule::deny)
+                .collect(),
+            fingerprint_method: FingerprintMethod::Masks,
+            dedup_threshold: None,
+            rssi_min_dbm: None,
+            rssi_max_dbm: None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ConfigError {
+    Invalid(&'static str),
+    UnsupportedVersion(u16),
+    Encode,
+    Storage(StoreError),
+}
+
+impl Config {
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.wifi_networks.len() > MAX_WIFI_NETWORKS {
+            return Err(ConfigError::Invalid("too many wifi_networks"));
+        }
+        for (index, network) in self.wifi_networks.iter().enumerate() {
+            if !(1..=32).contains(&network.ssid.len()) {
+                return Err(ConfigError::Invalid("wifi ssid must be 1 to 32 bytes"));
+            }
+            if !network.password.is_empty() && !(8..=64).contains(&network.password.len()) {
+                return Err(ConfigError::Invalid("wifi password must be 8 to 64 bytes"));
+            }
+            if self.wifi_networks[..index]
+                .iter()
+                .any(|n| n.ssid == network.ssid)
+            {
+                return Err(ConfigError::Invalid("duplicate wifi ssid"));
+            }
+        }
+        if self.wifi_failover_after_failures == 0 {
+            return Err(ConfigError::Invalid(
+                "wifi_failover_after_failures must be positive",
+            ));
+        }
+        if !(8..=64).contains(&self.portal_password.len()) {
+            return Err(ConfigError::Invalid(
+                "portal_password must be 8 to 64 bytes",
+            ));
+        }
+        if self.portal_timeout_secs < 60 {
+            return Err(ConfigError::Invalid("portal_timeout_secs below 60"));
+        }
+        if !tls::scheme_allowed(&self.api_url) {
+            return Err(ConfigError::Invalid(
+                "api_url must be an https URL, plain http needs an insecure-tls build",
+            ));
+        }
+        if self.api_url.len() > MAX_API_URL_LEN {
+            return Err(ConfigError::Invalid("api_url too long"));
+        }
+        if self.device_id.is_empty() || self.device_id.len() > 64 {
+            return Err(ConfigError::Invalid("device_id must be 1 to 64 bytes"));
+        }
+        // A config without it would buffer forever, the backend rejects unsigned uploads.
+        if self.device_secret.is_empty() {
+            return Err(ConfigError::Invalid("device_secret must be set"));
+        }
+        if tls::decode_pem_bundle(&self.api_ca_pem).is_none() {
+            return Err(ConfigError::Invalid("api_ca_pem is not a PEM certificate"));
+        }
+        if self.ntp_server.is_empty() {
+            return Err(ConfigError::Invalid("ntp_server must be set"));
+        }
+        if !(5..=3600).contains(&self.upload_period_secs) {
+            return Err(ConfigError::Invalid("upload_period_secs must be 5 to 3600"));
+        }
+        if self.send_timeout_secs == 0 || self.send_attempts == 0 {
+            return Err(ConfigError::Invalid(
+                "send timeout and attempts must be positive",
+            ));
+        }
+        if self.dns_reconnect_threshold > self.dns_restart_threshold {
+            return Err(ConfigError::Invalid(
+                "dns_reconnect_threshold above dns_restart_threshold",
+            ));
+        }
+        if self.max_bucket_span_secs < self.upload_period_secs as u64 {
+            return Err(ConfigError::Invalid(
+                "max_bucket_span_secs below the upload period",
+            ));
+        }
+        if self.hop_channels.is_empty() || self.hop_channels.iter().any(|c| !(1..=14).contains(c)) {
+            return Err(ConfigError::Invalid("hop_channels must be 1 to 14"));
+        }
+        if self.hop_channels.len() > MAX_HOP_CHANNELS {
+            return Err(ConfigError::Invalid("too many hop_channels"));
+        }
+        if !(50..=10_000).contains(&self.hop_dwell_ms) {
+            return Err(ConfigError::Invalid("hop_dwell_ms must be 50 to 10000"));
+        }
+        if self.mac_filter.len() > MAX_FILTER_RULES {
+            return Err(ConfigError::Invalid("too many mac_filter rules"));
+        }
+        if self
+            .mac_filter
+            .iter()
+            .any(|r| filter::parse_pattern(&r.pattern).is_none())
+        {
+            return Err(ConfigError::Invalid(
+                "mac_filter pattern must be an OUI or a full MAC",
+            ));
+        }
+        // Beyond the sum of all weights every fingerprint is within reach of every other one.
+        let max_distance: f32 = counter::bit_weights(self.fingerprint_method).iter().sum();
+        if self
+            .dedup_threshold
+            .is_some_and(|threshold| !(0.0..=max_distance).contains(&threshold))
+        {
+            return Err(ConfigError::Invalid(
+                "dedup_threshold must be 0 to the sum of the bit weights",
+            ));
+        }
+        if [self.rssi_min_dbm, self.rssi_max_dbm]
+            .into_iter()
+            .flatten()
+            .any(|dbm| dbm > 0)
+        {
+            return Err(ConfigError::Invalid("RSSI bounds must be 0 dBm or below"));
+        }
+        if self
+            .rssi_min_dbm
+            .zip(self.rssi_max_dbm)
+            .is_some_and(|(min, max)| min > max)
+        {
+            return Err(ConfigError::Invalid("rssi_min_dbm above rssi_max_dbm"));
+        }
+        Ok(())
+    }
+
+    pub fn upload_period(&self) -> Duration {
+        Duration::from_secs(self.upload_period_secs as u64)
+    }
+
+    pub fn send_timeout(&self) -> Duration {
+        Duration::from_secs(self.send_timeout_secs as u64)
+    }
+
+    pub fn portal_timeout(&self) -> Duration {
+        Duration::from_secs(self.portal_timeout_secs as u64)
+    }
+
+    pub fn send_retry_delay(&self) -> Duration {
+        Duration::from_millis(self.send_retry_delay_ms as u64)
+    }
+
+    pub fn hop_dwell(&self) -> Duration {
+        Duration::from_millis(self.hop_dwell_ms as u64)
+    }
+
+    pub fn dedup_threshold(&self) -> f32 {
+        self.dedup_threshold
+            .unwrap_or_else(|| counter::default_threshold(self.fingerprint_method))
+    }
+
+    pub fn eviction(&self) -> EvictionConfig {
+        EvictionConfig {
+            drop_zero_counts: self.drop_zero_count_packages,
+            max_bucket_span_secs: self.max_bucket_span_secs,
+        }
+    }
+
+    /// Makes `ssid` the highest priority network. An empty `password` keeps the stored one of a known network.
+    pub fn promote_wifi_network(&mut self, ssid: &str, password: &str) {
+        let existing = self
+            .wifi_networks
+            .iter()
+            .position(|n| n.ssid == ssid)
+            .map(|index| self.wifi_networks.remove(index));
+        let password = match existing {
+            Some(network) if password.is_empty() => network.password,
+            _ => password.to_string(),
+        };
+        self.wifi_networks.insert(
+            0,
+            WifiNetwork {
+                ssid: ssid.to_string(),
+                password,
+            },
+        );
+        self.wifi_networks.truncate(MAX_WIFI_NETWORKS);
+    }
+
+    /// Name shown to phones, `Trailsense-<last 4 chars of the device id>`.
+    pub fn node_name(&self) -> String {
+        // By characters, a byte offset could fall inside a multi-byte one.
+        let start = self
+            .device_id
+            .char_indices()
+            .rev()
+            .nth(3)
+            .map_or(0, |(index, _)| index);
+        let suffix = &self.device_id[start..];
+        format!("Trailsense-{}", suffix)
+    }
+}
+
+/// Characters of a derived password, without the look-alikes 0/o, 1/l/i so a label can be typed in.
+const PASSWORD_ALPHABET: &[u8] = b"abcdefghjkmnpqrstuvwxyz23456789";
+const DERIVED_PASSWORD_LEN: usize = 12;
+
+/// # Device Password
+///
+/// Portal password of a node that was not given one, HMAC-SHA256 of its base MAC keyed with the device secret,
+/// so the factory can print it on the label. `None` without a device secret, the MAC alone is broadcast by
+/// the access point and would not keep anyone out.
+pub fn device_password(device_secret: &[u8], mac: &[u8; 6]) -> Option<String> {
+    if device_secret.is_empty() {
+        return None;
+    }
+    // HMAC takes keys of any length, this cannot fail.
+    let mut hmac = Hmac::<Sha256>::new_from_slice(device_secret).expect("any key length");
+    hmac.update(b"trailsense-portal");
+    hmac.update(mac);
+    let password = hmac.finalize().into_bytes()[..DERIVED_PASSWORD_LEN]
+        .iter()
+        .map(|b| PASSWORD_ALPHABET[*b as usize % PASSWORD_ALPHABET.len()] as char)
+        .collect();
+    Some(password)
+}
+
+/// Accepts a stored config of the current schema only.
+pub fn check_version(config: Config) -> Result<Config, ConfigError> {
+    if config.version != CONFIG_VERSION {
+        return Err(ConfigError::UnsupportedVersion(config.version));
+    }
+    Ok(config)
+}
+
+static CONFIG: Mutex<CriticalSectionRawMutex, RefCell<Option<Arc<Config>>>> =
+    Mutex::new(RefCell::new(None));
+
+/// The current config, shared until the next update replaces it. Tasks read it where they use a value,
+/// so updates apply from their next cycle on.
+pub fn get() -> Arc<Config> {
+    CONFIG.lock(|c| c.borrow().clone()).unwrap_or_default()
+}
+
+/// Makes `config` current, for the loader and the update path of the firmware.
+pub fn set(config: Config) {
+    CONFIG.lock(|c| c.replace(Some(Arc::new(config))));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAC: [u8; 6] = [0x24, 0x0a, 0xc4, 0x12, 0x34, 0x56];
+
+    fn config_with_password(password: &str) -> Config {
+        Config {
+            portal_password: password.to_string(),
+            device_secret: "secret".to_string(),
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn device_password_is_stable_and_typeable() {
+        let password = device_password(b"secret", &MAC).unwrap();
+        assert_eq!(Some(&password), device_password(b"secret", &MAC).as_ref());
+        assert_eq!(password.len(), DERIVED_PASSWORD_LEN);
+        assert!(password.bytes().all(|b| PASSWORD_ALPHABET.contains(&b)));
+        assert!(config_with_password(&password).validate().is_ok());
+    }
+
+    #[test]
+    fn configs_without_a_device_secret_are_rejected() {
+        let config = Config {
+            device_secret: String::new(),
+            ..config_with_password("portal-password")
+        };
+        assert_eq!(
+            config.validate(),
+            Err(ConfigError::Invalid("device_secret must be set"))
+        );
+    }
+
+    #[test]
+    fn device_password_differs_per_device_and_secret() {
+        let mut other_mac = MAC;
+        other_mac[5] ^= 1;
+        let password = device_password(b"secret", &MAC);
+        assert_ne!(password, device_password(b"secret", &other_mac));
+        assert_ne!(password, device_password(b"other secret", &MAC));
+    }
+
+    #[test]
+    fn no_device_password_without_a_secret() {
+        assert_eq!(device_password(b"", &MAC), None);
+    }
+
+    #[test]
+    fn node_name_takes_the_last_four_characters() {
+        let node_name = |device_id: &str| {
+            Config {
+                device_id: device_id.to_string(),
+                ..Config::default()
+            }
+            .node_name()
+        };
+        assert_eq!(node_name("71ec4873-715f"), "Trailsense-715f");
+        assert_eq!(node_name("ab"), "Trailsense-ab");
+        assert_eq!(
+            node_name("node-\u{e9}t\u{e9}s"),
+            "Trailsense-\u{e9}t\u{e9}s"
+        );
+    }
+
+    #[test]
+    fn other_schema_versions_are_rejected() {
+        assert!(check_version(Config::default()).is_ok());
+        for version in [0, CONFIG_VERSION + 1] {
+            let config = Config {
+                version,
+                ..Config::default()
+            };
+            assert_eq!(
+                check_version(config),
+                Err(ConfigError::UnsupportedVersion(version))
+            );
+        }
+    }
+
+    #[test]
+    fn dedup_threshold_defaults_per_method_and_is_bounded_by_the_weights() {
+        let config = |fingerprint_method, dedup_threshold| Config {
+            fingerprint_method,
+            dedup_threshold,
+            ..config_with_password("portal-password")
+        };
+        assert_eq!(
+            config(FingerprintMethod::Masks, None).dedup_threshold(),
+            2.5
+        );
+        assert_eq!(
+            config(FingerprintMethod::Features, None).dedup_threshold(),
+            0.0
+        );
+        assert_eq!(
+            config(FingerprintMethod::Features, Some(1.0)).dedup_threshold(),
+            1.0
+        );
+
+        // Feature bits weigh 1 each, the mask alphas sum to about 19.1.
+        assert!(
+            config(FingerprintMethod::Features, Some(16.0))
+                .validate()
+                .is_ok()
+        );
+        assert!(
+            config(FingerprintMethod::Features, Some(17.0))
+                .validate()
+                .is_err()
+        );
+        assert!(
+            config(FingerprintMethod::Masks, Some(19.0))
+                .validate()
+                .is_ok()
+        );
+        assert!(
+            config(FingerprintMethod::Masks, Some(19.5))
+                .validate()
+                .is_err()
+        );
+        assert!(
+            config(FingerprintMethod::Masks, Some(-0.5))
+                .validate()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn portal_password_is_required() {
+        for password in ["", "short"] {
+            assert_eq!(
+                config_with_password(password).validate(),
+                Err(ConfigError::Invalid(
+                    "portal_password must be 8 to 64 bytes"
+                ))
+            );
+        }
+    }
+}
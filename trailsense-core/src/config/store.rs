@@ -0,0 +1,241 @@
+extern crate alloc;
+use alloc::vec::Vec;
+
+use embedded_storage::nor_flash::NorFlash;
+
+const SLOT_MAGIC: u32 = 0x5453_4346; // "TSCF"
+const SLOT_HEADER_LEN: usize = 16;
+const SLOTS: u32 = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StoreError {
+    Flash,
+    TooSmall,
+    TooLarge,
+}
+
+/// # Config Store
+///
+/// Two flash sectors used alternately, each holding one complete `[generation][len][crc32][magic]` header
+/// followed by the payload. A save erases and writes the older slot and programs the magic last,
+/// so a power loss mid-save always leaves the previous configuration readable.
+pub struct ConfigStore<F> {
+    flash: F,
+    generation: u32,
+    active: Option<u32>,
+}
+
+impl<F: NorFlash> ConfigStore<F> {
+    /// Opens the store and returns the newest intact payload, if any.
+    pub fn open(mut flash: F) -> Result<(Self, Option<Vec<u8>>), StoreError> {
+        if flash.capacity() < SLOTS as usize * F::ERASE_SIZE {
+            return Err(StoreError::TooSmall);
+        }
+
+        let mut newest: Option<(u32, u32, Vec<u8>)> = None;
+        for slot in 0..SLOTS {
+            if let Some((generation, payload)) = read_slot(&mut flash, slot)?
+                && newest.as_ref().is_none_or(|(g, _, _)| generation > *g)
+            {
+                newest = Some((generation, slot, payload));
+            }
+        }
+
+        Ok(match newest {
+            Some((generation, slot, payload)) => (
+                ConfigStore {
+                    flash,
+                    generation,
+                    active: Some(slot),
+                },
+                Some(payload),
+            ),
+            None => (
+                ConfigStore {
+                    flash,
+                    generation: 0,
+                    active: None,
+                },
+                None,
+            ),
+        })
+    }
+
+    pub fn save(&mut self, payload: &[u8]) -> Result<(), StoreError> {
+        if SLOT_HEADER_LEN + payload.len() > F::ERASE_SIZE {
+            return Err(StoreError::TooLarge);
+        }
+
+        let slot = match self.active {
+            Some(active) => (active + 1) % SLOTS,
+            None => 0,
+        };
+        let generation = self.generation.wrapping_add(1);
+        let base = slot * F::ERASE_SIZE as u32;
+
+        self.flash
+            .erase(base, base + F::ERASE_SIZE as u32)
+            .map_err(|_| StoreError::Flash)?;
+
+        let mut body = payload.to_vec();
+        body.resize(align_up(payload.len(), F::WRITE_SIZE), 0xFF);
+        self.flash
+            .write(base + SLOT_HEADER_LEN as u32, &body)
+            .map_err(|_| StoreError::Flash)?;
+
+        let mut header = [0u8; SLOT_HEADER_LEN];
+        header[0..4].copy_from_slice(&generation.to_le_bytes());
+        header[4..8].copy_from_slice(&(payload.len() as u32).to_le_bytes());
+        header[8..12].copy_from_slice(&crc32fast::hash(payload).to_le_bytes());
+        header[12..16].copy_from_slice(&SLOT_MAGIC.to_le_bytes());
+        self.flash
+            .write(base, &header)
+            .map_err(|_| StoreError::Flash)?;
+
+        self.generation = generation;
+        self.active = Some(slot);
+        Ok(())
+    }
+}
+
+fn read_slot<F: NorFlash>(flash: &mut F, slot: u32) -> Result<Option<(u32, Vec<u8>)>, StoreError> {
+    let base = slot * F::ERASE_SIZE as u32;
+    let mut header = [0u8; SLOT_HEADER_LEN];
+    flash
+        .read(base, &mut header)
+        .map_err(|_| StoreError::Flash)?;
+
+    let word =
+        |i: usize| u32::from_le_bytes([header[i], header[i + 1], header[i + 2], header[i + 3]]);
+    let (generation, len, crc, magic) = (word(0), word(4) as usize, word(8), word(12));
+    if magic != SLOT_MAGIC || SLOT_HEADER_LEN + align_up(len, F::READ_SIZE) > F::ERASE_SIZE {
+        return Ok(None);
+    }
+
+    let mut payload = alloc::vec![0u8; align_up(len, F::READ_SIZE)];
+    flash
+        .read(base + SLOT_HEADER_LEN as u32, &mut payload)
+        .map_err(|_| StoreError::Flash)?;
+    payload.truncate(len);
+    Ok((crc32fast::hash(&payload) == crc).then_some((generation, payload)))
+}
+
+fn align_up(value: usize, align: usize) -> usize {
+    value.div_ceil(align) * align
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embedded_storage::nor_flash::{
+        ErrorType, NorFlashErrorKind, ReadNorFlash, check_erase, check_read, check_write,
+    };
+
+    /// NOR flash in RAM that loses power after `budget` more written bytes.
+    struct MemFlash {
+        data: Vec<u8>,
+        budget: Option<usize>,
+    }
+
+    impl ErrorType for MemFlash {
+        type Error = NorFlashErrorKind;
+    }
+
+    impl ReadNorFlash for MemFlash {
+        const READ_SIZE: usize = 4;
+
+        fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+            check_read(self, offset, bytes.len())?;
+            let offset = offset as usize;
+            bytes.copy_from_slice(&self.data[offset..offset + bytes.len()]);
+            Ok(())
+        }
+
+        fn capacity(&self) -> usize {
+            self.data.len()
+        }
+    }
+
+    impl NorFlash for MemFlash {
+        const WRITE_SIZE: usize = 4;
+        const ERASE_SIZE: usize = 4096;
+
+        fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+            check_erase(self, from, to)?;
+            self.data[from as usize..to as usize].fill(0xFF);
+            Ok(())
+        }
+
+        fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+            check_write(self, offset, bytes.len())?;
+            for (cell, byte) in self.data[offset as usize..].iter_mut().zip(bytes) {
+                if let Some(budget) = self.budget.as_mut() {
+                    if *budget == 0 {
+                        return Err(NorFlashErrorKind::Other);
+                    }
+                    *budget -= 1;
+                }
+                assert_eq!(*cell, 0xFF, "write to unerased flash at {offset}");
+                *cell = *byte;
+            }
+            Ok(())
+        }
+    }
+
+    fn blank() -> MemFlash {
+        MemFlash {
+            data: alloc::vec![0xFF; 2 * 4096],
+            budget: None,
+        }
+    }
+
+    #[test]
+    fn blank_partition_has_no_config() {
+        let mut flash = blank();
+        assert_eq!(ConfigStore::open(&mut flash).unwrap().1, None);
+    }
+
+    #[test]
+    fn newest_save_wins() {
+        let mut flash = blank();
+        let (mut store, _) = ConfigStore::open(&mut flash).unwrap();
+        for payload in [&b"first"[..], b"second", b"third one"] {
+            store.save(payload).unwrap();
+        }
+        assert_eq!(
+            ConfigStore::open(&mut flash).unwrap().1.unwrap(),
+            b"third one"
+        );
+    }
+
+    #[test]
+    fn torn_save_keeps_the_previous_config() {
+        let mut flash = blank();
+        let mut last = b"initial".to_vec();
+        ConfigStore::open(&mut flash)
+            .unwrap()
+            .0
+            .save(&last)
+            .unwrap();
+
+        for round in 1..200usize {
+            let payload = alloc::format!("config {round} {}", "x".repeat(round % 37)).into_bytes();
+            // Power fails after a varying number of written bytes, including none and all of them.
+            flash.budget = Some(round * 7 % 120);
+            let saved = ConfigStore::open(&mut flash)
+                .unwrap()
+                .0
+                .save(&payload)
+                .is_ok();
+            flash.budget = None;
+
+            let restored = ConfigStore::open(&mut flash).unwrap().1.unwrap();
+            if saved {
+                assert_eq!(restored, payload, "round {round}");
+            } else {
+                assert!(restored == last || restored == payload, "round {round}");
+            }
+            last = restored;
+        }
+    }
+}
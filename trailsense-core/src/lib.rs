@@ -0,0 +1,10 @@
+#![cfg_attr(not(test), no_std)]
+
+pub mod ble;
+pub mod clock;
+pub mod config;
+pub mod network;
+pub mod packages;
+pub mod probes;
+pub mod rng;
+pub mod wifi;
@@ -0,0 +1,227 @@
+extern crate alloc;
+use alloc::vec::Vec;
+
+use log::{info, warn};
+
+use crate::{
+    network::{
+        UplinkTransport,
+        types::{ConnectionOutcome, SendDataOutcome},
+    },
+    packages::entity::PackageEntity,
+};
+
+pub struct FailoverConfig {
+    /// Successful upload cycles on a fallback link before the primary is tried again.
+    pub failback_after_cycles: u8,
+}
+
+impl Default for FailoverConfig {
+    fn default() -> Self {
+        Self {
+            failback_after_cycles: 10,
+        }
+    }
+}
+
+/// # Failover Transport
+///
+/// Ordered chain of uplinks, index 0 is the primary.
+/// A link is skipped when `ensure_connected` reports `Disconnected`/`Failure` or `send_data` reports `FatalFailure`.
+/// `TlsVerificationFailed` never moves on, the next link would hand the same data to a server that could not
+/// prove its identity, or to one that is not verified at all.
+pub struct FailoverTransport<T> {
+    links: Vec<T>,
+    active: usize,
+    healthy_cycles: u8,
+    failback_after_cycles: u8,
+}
+
+impl<T: UplinkTransport> FailoverTransport<T> {
+    pub fn new(links: Vec<T>, config: FailoverConfig) -> Self {
+        FailoverTransport {
+            links,
+            active: 0,
+            healthy_cycles: 0,
+            failback_after_cycles: config.failback_after_cycles,
+        }
+    }
+
+    pub fn active_index(&self) -> usize {
+        self.active
+    }
+
+    fn switch_to(&mut self, index: usize) {
+        if index == self.active {
+            return;
+        }
+        warn!(
+            "Uplink switching from link {} to link {}",
+            self.active, index
+        );
+        self.active = index;
+        self.healthy_cycles = 0;
+    }
+}
+
+impl<T: UplinkTransport> UplinkTransport for FailoverTransport<T> {
+    async fn ensure_connected(&mut self) -> ConnectionOutcome {
+        if self.active != 0 && self.healthy_cycles >= self.failback_after_cycles {
+            info!(
+                "Fallback link healthy for {} cycles, retrying primary",
+                self.healthy_cycles
+            );
+            self.switch_to(0);
+        }
+
+        let mut outcome = ConnectionOutcome::Failure;
+        for index in self.active..self.links.len() {
+            outcome = self.links[index].ensure_connected().await;
+            if matches!(outcome, ConnectionOutcome::Connected) {
+                self.switch_to(index);
+                return outcome;
+            }
+            warn!("Uplink link {} not connected", index);
+        }
+
+        // Nothing reachable, start from the primary again next cycle.
+        self.switch_to(0);
+        outcome
+    }
+
+    async fn send_data(&mut self, packages: &[PackageEntity]) -> SendDataOutcome {
+        let mut index = self.active;
+        while index < self.links.len() {
+            let is_last = index + 1 == self.links.len();
+            let outcome = self.links[index].send_data(packages).await;
+
+            match outcome {
+                SendDataOutcome::FatalFailure if !is_last => {
+                    warn!("Uplink link {} failed fatally, trying next link", index);
+                    index += 1;
+                    while index < self.links.len()
+                        && !matches!(
+                            self.links[index].ensure_connected().await,
+                            ConnectionOutcome::Connected
+                        )
+                    {
+                        index += 1;
+                    }
+                    if index == self.links.len() {
+                        self.switch_to(0);
+                        return outcome;
+                    }
+                    self.switch_to(index);
+                }
+                SendDataOutcome::Success(_) => {
+                    if self.active != 0 {
+                        self.healthy_cycles = self.healthy_cycles.saturating_add(1);
+                    }
+                    return outcome;
+                }
+                _ => return outcome,
+            }
+        }
+        SendDataOutcome::FatalFailure
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+    use std::{cell::RefCell, collections::VecDeque, rc::Rc};
+
+    /// Answers `send_data` from a script and records which link was asked.
+    struct Link {
+        name: &'static str,
+        connected: bool,
+        outcomes: VecDeque<SendDataOutcome>,
+        calls: Rc<RefCell<Vec<&'static str>>>,
+    }
+
+    impl UplinkTransport for Link {
+        async fn ensure_connected(&mut self) -> ConnectionOutcome {
+            if self.connected {
+                ConnectionOutcome::Connected
+            } else {
+                ConnectionOutcome::Disconnected
+            }
+        }
+
+        async fn send_data(&mut self, _packages: &[PackageEntity]) -> SendDataOutcome {
+            self.calls.borrow_mut().push(self.name);
+            self.outcomes
+                .pop_front()
+                .unwrap_or(SendDataOutcome::Success(Vec::new()))
+        }
+    }
+
+    fn chain(
+        primary: &[SendDataOutcome],
+        fallback: &[SendDataOutcome],
+    ) -> (FailoverTransport<Link>, Rc<RefCell<Vec<&'static str>>>) {
+        let calls = Rc::new(RefCell::new(Vec::new()));
+        let link = |name, outcomes: &[SendDataOutcome]| Link {
+            name,
+            connected: true,
+            outcomes: outcomes.iter().cloned().collect(),
+            calls: calls.clone(),
+        };
+        let transport = FailoverTransport::new(
+            alloc::vec![link("wifi", primary), link("gsm", fallback)],
+            FailoverConfig {
+                failback_after_cycles: 2,
+            },
+        );
+        (transport, calls)
+    }
+
+    #[test]
+    fn fatal_failure_moves_to_the_next_link_and_fails_back() {
+        let (mut transport, calls) = chain(&[SendDataOutcome::FatalFailure], &[]);
+        block_on(async {
+            transport.ensure_connected().await;
+            assert_eq!(
+                transport.send_data(&[]).await,
+                SendDataOutcome::Success(Vec::new())
+            );
+            assert_eq!(transport.active_index(), 1);
+            // The upload that failed over is the first healthy cycle of the fallback.
+            transport.ensure_connected().await;
+            transport.send_data(&[]).await;
+            assert_eq!(transport.active_index(), 1);
+            transport.ensure_connected().await;
+            assert_eq!(transport.active_index(), 0);
+        });
+        assert_eq!(*calls.borrow(), ["wifi", "gsm", "gsm"]);
+    }
+
+    #[test]
+    fn tls_verification_failure_does_not_fail_over() {
+        let (mut transport, calls) = chain(&[SendDataOutcome::TlsVerificationFailed], &[]);
+        block_on(async {
+            transport.ensure_connected().await;
+            assert_eq!(
+                transport.send_data(&[]).await,
+                SendDataOutcome::TlsVerificationFailed
+            );
+        });
+        assert_eq!(transport.active_index(), 0);
+        assert_eq!(*calls.borrow(), ["wifi"]);
+    }
+
+    #[test]
+    fn disconnected_primary_is_skipped() {
+        let (mut transport, calls) = chain(&[], &[]);
+        transport.links[0].connected = false;
+        block_on(async {
+            assert_eq!(
+                transport.ensure_connected().await,
+                ConnectionOutcome::Connected
+            );
+            transport.send_data(&[]).await;
+        });
+        assert_eq!(*calls.borrow(), ["gsm"]);
+    }
+}
@@ -0,0 +1,317 @@
+extern crate alloc;
+use alloc::{string::String, vec::Vec};
+
+use embassy_time::{Duration, Instant, WithTimeout};
+use embedded_io_async::{Read, Write};
+
+const AT_RX_BUFFER: usize = 512;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AtError {
+    Io,
+    Timeout,
+    Error,
+    Overflow,
+}
+
+/// # AT Client
+///
+/// Line based AT command client for SIMCom style modems.
+/// Works on any `embedded-io-async` byte stream, so a scripted fake modem can stand in for the UART.
+pub struct AtClient<T> {
+    io: T,
+    buffer: [u8; AT_RX_BUFFER],
+    len: usize,
+}
+
+impl<T: Read + Write> AtClient<T> {
+    pub fn new(io: T) -> Self {
+        AtClient {
+            io,
+            buffer: [0; AT_RX_BUFFER],
+            len: 0,
+        }
+    }
+
+    #[cfg(test)]
+    pub fn io(&self) -> &T {
+        &self.io
+    }
+
+    /// Sends `cmd` and collects the response lines until the final result code.
+    /// Echoed commands and empty lines are skipped, `ERROR`, `+CME ERROR` and `+CMS ERROR` map to `AtError::Error`.
+    pub async fn command(&mut self, cmd: &str, timeout: Duration) -> Result<Vec<String>, AtError> {
+        self.len = 0;
+        self.write_raw(cmd.as_bytes()).await?;
+        self.write_raw(b"\r").await?;
+
+        let deadline = Instant::now() + timeout;
+        let mut lines = Vec::new();
+        loop {
+            let line = self.read_line(deadline).await?;
+            if line.is_empty() || line == cmd {
+                continue;
+            }
+            if line == "OK" {
+                return Ok(lines);
+            }
+            if is_error(&line) {
+                return Err(AtError::Error);
+            }
+            lines.push(line);
+        }
+    }
+
+    /// Waits for an unsolicited line starting with `prefix`, e.g. `+HTTPACTION:` or `DOWNLOAD`.
+    pub async fn wait_for(&mut self, prefix: &str, timeout: Duration) -> Result<String, AtError> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let line = self.read_line(deadline).await?;
+            if line.starts_with(prefix) {
+                return Ok(line);
+            }
+            if is_error(&line) {
+                return Err(AtError::Error);
+            }
+        }
+    }
+
+    /// Waits for the `>` prompt of a command that takes raw data, which comes without a line ending.
+    pub async fn wait_for_prompt(&mut self, timeout: Duration) -> Result<(), AtError> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(at) = self.buffer[..self.len].iter().position(|&b| b == b'>') {
+                self.buffer.copy_within(at + 1..self.len, 0);
+                self.len -= at + 1;
+                return Ok(());
+            }
+            if self.buffer[..self.len].contains(&b'\n') {
+                if is_error(&self.read_line(deadline).await?) {
+                    return Err(AtError::Error);
+                }
+                continue;
+            }
+            self.fill(deadline).await?;
+        }
+    }
+
+    pub async fn write_raw(&mut self, data: &[u8]) -> Result<(), AtError> {
+        self.io.write_all(data).await.map_err(|_| AtError::Io)?;
+        self.io.flush().await.map_err(|_| AtError::Io)
+    }
+
+    async fn read_line(&mut self, deadline: Instant) -> Result<String, AtError> {
+        loop {
+            if let Some(end) = self.buffer[..self.len].iter().position(|&b| b == b'\n') {
+                let line = String::from_utf8_lossy(&self.buffer[..end])
+                    .trim_end_matches('\r')
+                    .into();
+                self.buffer.copy_within(end + 1..self.len, 0);
+                self.len -= end + 1;
+                return Ok(line);
+            }
+
+            self.fill(deadline).await?;
+        }
+    }
+
+    /// Appends the next read to the buffer.
+    async fn fill(&mut self, deadline: Instant) -> Result<(), AtError> {
+        if self.len == self.buffer.len() {
+            self.len = 0;
+            return Err(AtError::Overflow);
+        }
+
+        let read = match self
+            .io
+            .read(&mut self.buffer[self.len..])
+            .with_deadline(deadline)
+            .await
+        {
+            Ok(Ok(n)) => n,
+            Ok(Err(_)) => return Err(AtError::Io),
+            Err(_) => return Err(AtError::Timeout),
+        };
+        if read == 0 {
+            return Err(AtError::Io);
+        }
+        self.len += read;
+        Ok(())
+    }
+}
+
+fn is_error(line: &str) -> bool {
+    line == "ERROR" || line.starts_with("+CME ERROR") || line.starts_with("+CMS ERROR")
+}
+
+/// Scripted stand-in for the modem UART, shared by the AT and GSM transport tests.
+#[cfg(test)]
+pub mod fake {
+    use std::collections::VecDeque;
+
+    use embassy_time::{Duration, Timer};
+
+    /// Answers each expected command with its reply once everything written since the previous match
+    /// contains it. Reads with nothing queued never return, so they run into the caller's timeout.
+    pub struct FakeModem {
+        script: VecDeque<(&'static str, &'static str)>,
+        pending: VecDeque<u8>,
+        unmatched: Vec<u8>,
+        written: Vec<u8>,
+    }
+
+    impl FakeModem {
+        pub fn new(script: &[(&'static str, &'static str)]) -> Self {
+            FakeModem {
+                script: script.iter().copied().collect(),
+                pending: VecDeque::new(),
+                unmatched: Vec::new(),
+                written: Vec::new(),
+            }
+        }
+
+        pub fn finished(&self) -> bool {
+            self.script.is_empty()
+        }
+
+        pub fn written(&self) -> String {
+            String::from_utf8_lossy(&self.written).into_owned()
+        }
+    }
+
+    impl embedded_io_async::ErrorType for FakeModem {
+        type Error = core::convert::Infallible;
+    }
+
+    impl embedded_io_async::Read for FakeModem {
+        async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+            if self.pending.is_empty() {
+                Timer::after(Duration::from_secs(3600)).await;
+            }
+            // Short reads, so lines arrive split like they do from a UART.
+            let len = buf.len().min(self.pending.len()).min(5);
+            for byte in buf.iter_mut().take(len) {
+                *byte = self.pending.pop_front().unwrap_or_default();
+            }
+            Ok(len)
+        }
+    }
+
+    impl embedded_io_async::Write for FakeModem {
+        async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+            self.written.extend_from_slice(buf);
+            self.unmatched.extend_from_slice(buf);
+            let matched = self.script.front().is_some_and(|(expected, _)| {
+                String::from_utf8_lossy(&self.unmatched).contains(expected)
+            });
+            if let Some((_, reply)) = matched.then(|| self.script.pop_front()).flatten() {
+                self.pending.extend(reply.bytes());
+                self.unmatched.clear();
+            }
+            Ok(buf.len())
+        }
+
+        async fn flush(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{fake::FakeModem, *};
+    use futures::executor::block_on;
+
+    const TIMEOUT: Duration = Duration::from_millis(200);
+
+    #[test]
+    fn command_collects_lines_until_ok() {
+        let modem = FakeModem::new(&[("AT+CREG?\r", "AT+CREG?\r\r\n+CREG: 0,5\r\n\r\nOK\r\n")]);
+        let mut at = AtClient::new(modem);
+        let lines = block_on(at.command("AT+CREG?", TIMEOUT)).unwrap();
+        assert_eq!(lines, ["+CREG: 0,5"]);
+    }
+
+    #[test]
+    fn command_maps_error_result_codes() {
+        let modem = FakeModem::new(&[
+            ("AT+A\r", "\r\nERROR\r\n"),
+            ("AT+B\r", "\r\n+CME ERROR: 3\r\n"),
+            ("AT+C\r", "\r\n+CMS ERROR: 500\r\n"),
+        ]);
+        let mut at = AtClient::new(modem);
+        for cmd in ["AT+A", "AT+B", "AT+C"] {
+            assert_eq!(block_on(at.command(cmd, TIMEOUT)), Err(AtError::Error));
+        }
+    }
+
+    #[test]
+    fn command_times_out_without_result_code() {
+        let modem = FakeModem::new(&[("AT\r", "\r\n+PARTIAL\r\n")]);
+        let mut at = AtClient::new(modem);
+        assert_eq!(block_on(at.command("AT", TIMEOUT)), Err(AtError::Timeout));
+    }
+
+    #[test]
+    fn wait_for_skips_other_lines() {
+        let modem = FakeModem::new(&[(
+            "AT+HTTPACTION=1\r",
+            "\r\nOK\r\n\r\n+CSQ: 20,0\r\n+HTTPACTION: 1,200,15\r\n",
+        )]);
+        let mut at = AtClient::new(modem);
+        block_on(async {
+            at.command("AT+HTTPACTION=1", TIMEOUT).await.unwrap();
+            let line = at.wait_for("+HTTPACTION:", TIMEOUT).await.unwrap();
+            assert_eq!(line, "+HTTPACTION: 1,200,15");
+        });
+    }
+
+    #[test]
+    fn wait_for_stops_at_error() {
+        let modem = FakeModem::new(&[("AT+HTTPDATA\r", "\r\nERROR\r\n")]);
+        let mut at = AtClient::new(modem);
+        block_on(async {
+            at.write_raw(b"AT+HTTPDATA\r").await.unwrap();
+            assert_eq!(at.wait_for("DOWNLOAD", TIMEOUT).await, Err(AtError::Error));
+        });
+    }
+
+    #[test]
+    fn wait_for_prompt_needs_no_line_ending() {
+        let modem = FakeModem::new(&[("AT+CCERTDOWN=\"ca.pem\",4\r", "\r\n>")]);
+        let mut at = AtClient::new(modem);
+        block_on(async {
+            at.write_raw(b"AT+CCERTDOWN=\"ca.pem\",4\r").await.unwrap();
+            assert_eq!(at.wait_for_prompt(TIMEOUT).await, Ok(()));
+        });
+    }
+
+    #[test]
+    fn wait_for_prompt_stops_at_error() {
+        let modem = FakeModem::new(&[("AT+CCERTDOWN\r", "\r\n+CME ERROR: 4\r\n")]);
+        let mut at = AtClient::new(modem);
+        block_on(async {
+            at.write_raw(b"AT+CCERTDOWN\r").await.unwrap();
+            assert_eq!(at.wait_for_prompt(TIMEOUT).await, Err(AtError::Error));
+        });
+    }
+
+    #[test]
+    fn read_line_keeps_the_rest_of_a_read() {
+        // Both lines arrive before the first is parsed, the second must survive for the next call.
+        let modem = FakeModem::new(&[("AT\r", "\r\nOK\r\nRDY\r\n")]);
+        let mut at = AtClient::new(modem);
+        block_on(async {
+            at.command("AT", TIMEOUT).await.unwrap();
+            assert_eq!(at.wait_for("RDY", TIMEOUT).await.unwrap(), "RDY");
+        });
+    }
+
+    #[test]
+    fn read_line_overflows_on_endless_line() {
+        let line = format!("{}\r\n", "x".repeat(AT_RX_BUFFER + 1)).leak();
+        let modem = FakeModem::new(&[("AT\r", line)]);
+        let mut at = AtClient::new(modem);
+        assert_eq!(block_on(at.command("AT", TIMEOUT)), Err(AtError::Overflow));
+    }
+}
@@ -0,0 +1,2 @@
+pub mod at;
+pub mod transport;
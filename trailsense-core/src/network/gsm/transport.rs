@@ -0,0 +1,629 @@
+extern crate alloc;
+use alloc::string::String;
+use core::fmt::Write as _;
+
+use embassy_time::{Duration, Instant, Timer};
+use embedded_io_async::{Read, Write};
+use log::{error, info, warn};
+
+use crate::{
+    config,
+    network::{
+        UplinkTransport, acknowledged_seqs, auth, encode_payload,
+        gsm::at::{AtClient, AtError},
+        tls,
+        types::{ConnectionOutcome, SendDataOutcome},
+    },
+    packages::entity::PackageEntity,
+};
+
+const SHORT_TIMEOUT: Duration = Duration::from_secs(2);
+const ATTACH_TIMEOUT: Duration = Duration::from_secs(20);
+const BEARER_TIMEOUT: Duration = Duration::from_secs(30);
+const HTTP_DATA_TIMEOUT: Duration = Duration::from_secs(10);
+const HTTP_ACTION_TIMEOUT: Duration = Duration::from_secs(60);
+const REGISTRATION_POLL_INTERVAL: Duration = Duration::from_secs(1);
+const PROBE_ATTEMPTS: u8 = 3;
+/// Modem file the trust anchors are stored in, for the SSL context the HTTP client uses.
+const CA_FILE: &str = "trailsense_ca.pem";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ModemKind {
+    /// SIM800/SIM900, bearer via `AT+SAPBR`.
+    Sim800,
+    /// SIM7600/A7670, PDP context via `AT+CGDCONT`/`AT+CGACT`.
+    Sim7600,
+}
+
+pub struct GsmTransportConfig {
+    pub apn: String,
+    pub modem: ModemKind,
+    pub registration_timeout: Duration,
+    pub reset_threshold: u8,
+}
+
+impl Default for GsmTransportConfig {
+    fn default() -> Self {
+        Self {
+            apn: config::get().gsm_apn.clone(),
+            modem: ModemKind::Sim800,
+            registration_timeout: Duration::from_secs(60),
+            reset_threshold: 4,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum HttpStatus {
+    Accepted,
+    Retryable,
+    Rejected,
+    Unverified,
+}
+
+/// Maps the status of `+HTTPACTION`, which also reports modem side failures as 6xx on the SIM800
+/// (e.g. 601 network error, 603 DNS error) and 7xx on the SIM7600, where 715 is a failed handshake
+/// and 719 a missing CA file. With server authentication on, a failed handshake is taken for a failed verification.
+fn classify_status(status: u16) -> HttpStatus {
+    match status {
+        200..=299 => HttpStatus::Accepted,
+        500..=699 => HttpStatus::Retryable,
+        715 | 719 => HttpStatus::Unverified,
+        _ => HttpStatus::Rejected,
+    }
+}
+
+pub struct GsmTransport<T> {
+    at: AtClient<T>,
+    config: GsmTransportConfig,
+    bearer_open: bool,
+    consecutive_failures: u8,
+    recovery_pending: bool,
+    /// CRC of the trust anchor bundle the modem verifies against, `None` until it was loaded.
+    loaded_anchors: Option<u32>,
+}
+
+impl<T: Read + Write> GsmTransport<T> {
+    pub fn new(io: T, config: GsmTransportConfig) -> Self {
+        GsmTransport {
+            at: AtClient::new(io),
+            config,
+            bearer_open: false,
+            consecutive_failures: 0,
+            recovery_pending: false,
+            loaded_anchors: None,
+        }
+    }
+
+    async fn probe_modem(&mut self) -> bool {
+        for _ in 0..PROBE_ATTEMPTS {
+            if self.at.command("AT", SHORT_TIMEOUT).await.is_ok() {
+                // Echo off keeps response parsing simple, failure is harmless since echoes are skipped.
+                let _ = self.at.command("ATE0", SHORT_TIMEOUT).await;
+                return true;
+            }
+        }
+        false
+    }
+
+    async fn wait_for_registration(&mut self) -> bool {
+        let deadline = Instant::now() + self.config.registration_timeout;
+        while Instant::now() < deadline {
+            if let Ok(lines) = self.at.command("AT+CREG?", SHORT_TIMEOUT).await {
+                // +CREG: <n>,<stat> where 1 = home network and 5 = roaming.
+                let registered = lines.iter().any(|l| {
+                    l.strip_prefix("+CREG:")
+                        .and_then(|v| v.split(',').nth(1))
+                        .is_some_and(|stat| matches!(stat.trim(), "1" | "5"))
+                });
+                if registered {
+                    return true;
+                }
+            }
+            Timer::after(REGISTRATION_POLL_INTERVAL).await;
+        }
+        false
+    }
+
+    async fn open_bearer(&mut self) -> Result<(), AtError> {
+        let mut cmd = heapless::String::<96>::new();
+        match self.config.modem {
+            ModemKind::Sim800 => {
+                self.at
+                    .command("AT+SAPBR=3,1,\"CONTYPE\",\"GPRS\"", SHORT_TIMEOUT)
+                    .await?;
+                write!(cmd, "AT+SAPBR=3,1,\"APN\",\"{}\"", self.config.apn)
+                    .map_err(|_| AtError::Overflow)?;
+                self.at.command(&cmd, SHORT_TIMEOUT).await?;
+                // Opening an already open bearer answers ERROR, so check the state afterwards instead.
+                let _ = self.at.command("AT+SAPBR=1,1", BEARER_TIMEOUT).await;
+                let lines = self.at.command("AT+SAPBR=2,1", SHORT_TIMEOUT).await?;
+                let open = lines.iter().any(|l| l.starts_with("+SAPBR: 1,1"));
+                if !open {
+                    return Err(AtError::Error);
+                }
+            }
+            ModemKind::Sim7600 => {
+                write!(cmd, "AT+CGDCONT=1,\"IP\",\"{}\"", self.config.apn)
+                    .map_err(|_| AtError::Overflow)?;
+                self.at.command(&cmd, SHORT_TIMEOUT).await?;
+                self.at.command("AT+CGACT=1,1", BEARER_TIMEOUT).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Stores the trust anchors on a SIM7600 and has SSL context 0 verify the server against them.
+    /// The certificate file lives in the modem's flash, so it is only written again when the bundle
+    /// changed or the modem was reset.
+    async fn load_trust_anchors(&mut self) -> Result<(), AtError> {
+        let pem = tls::encode_pem_bundle(&tls::trust_anchors());
+        let crc = crc32fast::hash(pem.as_bytes());
+        if self.loaded_anchors == Some(crc) {
+            return Ok(());
+        }
+
+        let mut cmd = heapless::String::<64>::new();
+        write!(cmd, "AT+CCERTDOWN=\"{}\",{}\r", CA_FILE, pem.len())
+            .map_err(|_| AtError::Overflow)?;
+        self.at.write_raw(cmd.as_bytes()).await?;
+        self.at.wait_for_prompt(SHORT_TIMEOUT).await?;
+        self.at.write_raw(pem.as_bytes()).await?;
+        self.at.wait_for("OK", HTTP_DATA_TIMEOUT).await?;
+
+        // Any TLS version, authentication mode 1 verifies the server, SNI selects its certificate.
+        self.at
+            .command("AT+CSSLCFG=\"sslversion\",0,4", SHORT_TIMEOUT)
+            .await?;
+        self.at
+            .command("AT+CSSLCFG=\"authmode\",0,1", SHORT_TIMEOUT)
+            .await?;
+        cmd.clear();
+        write!(cmd, "AT+CSSLCFG=\"cacert\",0,\"{}\"", CA_FILE).map_err(|_| AtError::Overflow)?;
+        self.at.command(&cmd, SHORT_TIMEOUT).await?;
+        self.at
+            .command("AT+CSSLCFG=\"enableSNI\",0,1", SHORT_TIMEOUT)
+            .await?;
+
+        info!("Loaded {} bytes of trust anchors into the modem", pem.len());
+        self.loaded_anchors = Some(crc);
+        Ok(())
+    }
+
+    /// Returns the HTTP status and the response body.
+    async fn http_post(
+        &mut self,
+        url: &str,
+        headers: &[(&str, &str)],
+        body: &[u8],
+    ) -> Result<(u16, String), AtError> {
+        // Clear a session left over from an interrupted upload, ERROR just means there was none.
+        let _ = self.at.command("AT+HTTPTERM", SHORT_TIMEOUT).await;
+        self.at.command("AT+HTTPINIT", SHORT_TIMEOUT).await?;
+
+        let mut cmd = heapless::String::<192>::new();
+        if self.config.modem == ModemKind::Sim800 {
+            self.at
+                .command("AT+HTTPPARA=\"CID\",1", SHORT_TIMEOUT)
+                .await?;
+            if url.starts_with("https://") {
+                // Encrypts without verifying the server, `send_data` only gets here in `insecure-tls` builds.
+                self.at.command("AT+HTTPSSL=1", SHORT_TIMEOUT).await?;
+            }
+        }
+        write!(cmd, "AT+HTTPPARA=\"URL\",\"{}\"", url).map_err(|_| AtError::Overflow)?;
+        self.at.command(&cmd, SHORT_TIMEOUT).await?;
+        if self.config.modem == ModemKind::Sim7600 && url.starts_with("https://") {
+            // The context `load_trust_anchors` configured.
+            self.at
+                .command("AT+HTTPPARA=\"SSLCFG\",0", SHORT_TIMEOUT)
+                .await?;
+        }
+        self.at
+            .command(
+                "AT+HTTPPARA=\"CONTENT\",\"application/json\"",
+                SHORT_TIMEOUT,
+            )
+            .await?;
+        if !headers.is_empty() {
+            // Extra headers go into USERDATA, separated by a literal `\r\n` the firmware expands.
+            let mut user_data = heapless::String::<320>::new();
+            write!(user_data, "AT+HTTPPARA=\"USERDATA\",\"").map_err(|_| AtError::Overflow)?;
+            for (index, (name, value)) in headers.iter().enumerate() {
+                if index > 0 {
+                    write!(user_data, "\\r\\n").map_err(|_| AtError::Overflow)?;
+                }
+                write!(user_data, "{}: {}", name, value).map_err(|_| AtError::Overflow)?;
+            }
+            write!(user_data, "\"").map_err(|_| AtError::Overflow)?;
+            self.at.command(&user_data, SHORT_TIMEOUT).await?;
+        }
+
+        cmd.clear();
+        write!(
+            cmd,
+            "AT+HTTPDATA={},{}\r",
+            body.len(),
+            HTTP_DATA_TIMEOUT.as_millis()
+        )
+        .map_err(|_| AtError::Overflow)?;
+        self.at.write_raw(cmd.as_bytes()).await?;
+        self.at.wait_for("DOWNLOAD", SHORT_TIMEOUT).await?;
+        self.at.write_raw(body).await?;
+        self.at.wait_for("OK", HTTP_DATA_TIMEOUT).await?;
+
+        self.at.command("AT+HTTPACTION=1", SHORT_TIMEOUT).await?;
+        // +HTTPACTION: <method>,<status>,<datalen>
+        let action = self
+            .at
+            .wait_for("+HTTPACTION:", HTTP_ACTION_TIMEOUT)
+            .await?;
+        let status = action
+            .split(',')
+            .nth(1)
+            .and_then(|s| s.trim().parse::<u16>().ok())
+            .ok_or(AtError::Error)?;
+
+        let mut response = String::new();
+        if let Ok(lines) = self.at.command("AT+HTTPREAD", HTTP_DATA_TIMEOUT).await {
+            for line in lines.iter().filter(|l| !l.starts_with("+HTTPREAD")) {
+                response.push_str(line);
+            }
+        }
+        info!("GSM response ({}): {}", status, response);
+        let _ = self.at.command("AT+HTTPTERM", SHORT_TIMEOUT).await;
+
+        Ok((status, response))
+    }
+}
+
+impl<T: Read + Write> UplinkTransport for GsmTransport<T> {
+    async fn ensure_connected(&mut self) -> ConnectionOutcome {
+        let was_recovering = self.recovery_pending;
+
+        if !self.probe_modem().await {
+            error!("GSM modem not responding");
+            return ConnectionOutcome::Failure;
+        }
+
+        if !self.wait_for_registration().await {
+            error!("GSM network registration timeout");
+            self.bearer_open = false;
+            return ConnectionOutcome::Disconnected;
+        }
+
+        if let Err(e) = self.at.command("AT+CGATT=1", ATTACH_TIMEOUT).await {
+            error!("GPRS attach failed: {:?}", e);
+            self.bearer_open = false;
+            return ConnectionOutcome::Disconnected;
+        }
+
+        if !self.bearer_open {
+            if let Err(e) = self.open_bearer().await {
+                error!("Failed to open GSM data bearer: {:?}", e);
+                return ConnectionOutcome::Disconnected;
+            }
+            info!("GSM data bearer open (APN '{}')", self.config.apn);
+            self.bearer_open = true;
+        }
+
+        if was_recovering {
+            info!("GSM recovery completed");
+            self.recovery_pending = false;
+            self.consecutive_failures = 0;
+        }
+
+        ConnectionOutcome::Connected
+    }
+
+    async fn send_data(&mut self, packages: &[PackageEntity]) -> SendDataOutcome {
+        if self.recovery_pending {
+            return SendDataOutcome::BackoffRequired;
+        }
+
+        if self.consecutive_failures >= self.config.reset_threshold {
+            warn!("Too many GSM failures, resetting modem");
+            if let Err(e) = self.at.command("AT+CFUN=1,1", SHORT_TIMEOUT).await {
+                error!("Failed to reset GSM modem: {:?}", e);
+            }
+            self.recovery_pending = true;
+            self.bearer_open = false;
+            self.consecutive_failures = 0;
+            self.loaded_anchors = None;
+            return SendDataOutcome::BackoffRequired;
+        }
+
+        let mut url = heapless::String::<128>::new();
+        if let Err(e) = write!(&mut url, "{}/ingest", config::get().api_url) {
+            error!("Failed to generate URL: {}", e);
+            return SendDataOutcome::FatalFailure;
+        }
+
+        if !tls::scheme_allowed(&url) {
+            error!("Refusing to upload to '{}' without TLS", url.as_str());
+            return SendDataOutcome::FatalFailure;
+        }
+        if url.starts_with("https://") {
+            match self.config.modem {
+                ModemKind::Sim7600 => {
+                    if let Err(e) = self.load_trust_anchors().await {
+                        error!("Failed to load the trust anchors into the modem: {:?}", e);
+                        self.consecutive_failures += 1;
+                        return SendDataOutcome::RetryableFailure;
+                    }
+                }
+                // Encrypts but cannot check a certificate, so the next link gets its turn.
+                ModemKind::Sim800 if !tls::ALLOW_UNVERIFIED => {
+                    error!("SIM800 cannot verify the server certificate, refusing to upload");
+                    return SendDataOutcome::FatalFailure;
+                }
+                ModemKind::Sim800 => {}
+            }
+        }
+
+        let body = match encode_payload(packages) {
+            Ok(v) => v,
+            Err(e) => {
+                error!("Failed to serialize payload: {:?}", e);
+                return SendDataOutcome::FatalFailure;
+            }
+        };
+
+        let auth = match auth::sign_request(&body) {
+            Ok(auth) => auth,
+            Err(e) => {
+                error!("Cannot sign ingest request, holding packages back: {:?}", e);
+                return SendDataOutcome::BackoffRequired;
+            }
+        };
+
+        let (status, response) = match self.http_post(url.as_str(), &auth.pairs(), &body).await {
+            Ok(s) => s,
+            Err(e) => {
+                error!(
+                    "GSM HTTP POST failed: url='{}', payload_len={}, err={:?}",
+                    url.as_str(),
+                    body.len(),
+                    e
+                );
+                self.consecutive_failures += 1;
+                self.bearer_open = false;
+                return SendDataOutcome::RetryableFailure;
+            }
+        };
+
+        match classify_status(status) {
+            HttpStatus::Accepted => {
+                self.consecutive_failures = 0;
+                SendDataOutcome::Success(acknowledged_seqs(packages, &response))
+            }
+            HttpStatus::Retryable => {
+                error!("GSM HTTP error status: {}", status);
+                self.consecutive_failures += 1;
+                SendDataOutcome::RetryableFailure
+            }
+            HttpStatus::Rejected => {
+                error!("GSM HTTP error status: {}", status);
+                self.consecutive_failures = 0;
+                SendDataOutcome::FatalFailure
+            }
+            HttpStatus::Unverified => {
+                error!("GSM TLS handshake failed ({}), server not verified", status);
+                self.consecutive_failures = 0;
+                SendDataOutcome::TlsVerificationFailed
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::gsm::at::fake::FakeModem;
+    use futures::executor::block_on;
+
+    fn transport(
+        modem: ModemKind,
+        script: &[(&'static str, &'static str)],
+    ) -> GsmTransport<FakeModem> {
+        GsmTransport::new(
+            FakeModem::new(script),
+            GsmTransportConfig {
+                apn: "internet".into(),
+                modem,
+                registration_timeout: Duration::from_secs(1),
+                reset_threshold: 4,
+            },
+        )
+    }
+
+    const REGISTERED: [(&str, &str); 4] = [
+        ("AT\r", "\r\nOK\r\n"),
+        ("ATE0\r", "\r\nOK\r\n"),
+        ("AT+CREG?\r", "\r\n+CREG: 0,5\r\n\r\nOK\r\n"),
+        ("AT+CGATT=1\r", "\r\nOK\r\n"),
+    ];
+
+    #[test]
+    fn sim800_bring_up_opens_sapbr_bearer() {
+        let bearer = [
+            ("AT+SAPBR=3,1,\"CONTYPE\",\"GPRS\"\r", "\r\nOK\r\n"),
+            ("AT+SAPBR=3,1,\"APN\",\"internet\"\r", "\r\nOK\r\n"),
+            // Already open, which the state query below has to catch.
+            ("AT+SAPBR=1,1\r", "\r\nERROR\r\n"),
+            (
+                "AT+SAPBR=2,1\r",
+                "\r\n+SAPBR: 1,1,\"10.0.0.2\"\r\n\r\nOK\r\n",
+            ),
+        ];
+        let mut gsm = transport(ModemKind::Sim800, &[&REGISTERED[..], &bearer].concat());
+        assert_eq!(
+            block_on(gsm.ensure_connected()),
+            ConnectionOutcome::Connected
+        );
+        assert!(gsm.at.io().finished());
+    }
+
+    #[test]
+    fn sim800_bring_up_fails_without_bearer_address() {
+        let bearer = [
+            ("AT+SAPBR=3,1,\"CONTYPE\",\"GPRS\"\r", "\r\nOK\r\n"),
+            ("AT+SAPBR=3,1,\"APN\",\"internet\"\r", "\r\nOK\r\n"),
+            ("AT+SAPBR=1,1\r", "\r\nERROR\r\n"),
+            (
+                "AT+SAPBR=2,1\r",
+                "\r\n+SAPBR: 1,3,\"0.0.0.0\"\r\n\r\nOK\r\n",
+            ),
+        ];
+        let mut gsm = transport(ModemKind::Sim800, &[&REGISTERED[..], &bearer].concat());
+        assert_eq!(
+            block_on(gsm.ensure_connected()),
+            ConnectionOutcome::Disconnected
+        );
+        assert!(!gsm.bearer_open);
+    }
+
+    #[test]
+    fn sim7600_bring_up_activates_pdp_context() {
+        let bearer = [
+            ("AT+CGDCONT=1,\"IP\",\"internet\"\r", "\r\nOK\r\n"),
+            ("AT+CGACT=1,1\r", "\r\nOK\r\n"),
+        ];
+        let mut gsm = transport(ModemKind::Sim7600, &[&REGISTERED[..], &bearer].concat());
+        assert_eq!(
+            block_on(gsm.ensure_connected()),
+            ConnectionOutcome::Connected
+        );
+        assert!(gsm.at.io().finished());
+        assert!(!gsm.at.io().written().contains("SAPBR"));
+    }
+
+    #[test]
+    fn bring_up_waits_for_registration() {
+        let script = [
+            ("AT\r", "\r\nOK\r\n"),
+            ("ATE0\r", "\r\nOK\r\n"),
+            ("AT+CREG?\r", "\r\n+CREG: 0,2\r\n\r\nOK\r\n"),
+        ];
+        let mut gsm = transport(ModemKind::Sim7600, &script);
+        assert_eq!(
+            block_on(gsm.ensure_connected()),
+            ConnectionOutcome::Disconnected
+        );
+    }
+
+    #[test]
+    fn http_post_reads_status_and_body() {
+        let script = [
+            ("AT+HTTPTERM\r", "\r\nERROR\r\n"),
+            ("AT+HTTPINIT\r", "\r\nOK\r\n"),
+            ("AT+HTTPPARA=\"CID\",1\r", "\r\nOK\r\n"),
+            (
+                "AT+HTTPPARA=\"URL\",\"http://example.org/ingest\"\r",
+                "\r\nOK\r\n",
+            ),
+            (
+                "AT+HTTPPARA=\"CONTENT\",\"application/json\"\r",
+                "\r\nOK\r\n",
+            ),
+            (
+                "AT+HTTPPARA=\"USERDATA\",\"X-A: 1\\r\\nX-B: 2\"\r",
+                "\r\nOK\r\n",
+            ),
+            ("AT+HTTPDATA=2,10000\r", "\r\nDOWNLOAD\r\n"),
+            ("[]", "\r\nOK\r\n"),
+            (
+                "AT+HTTPACTION=1\r",
+                "\r\nOK\r\n\r\n+HTTPACTION: 1,503,12\r\n",
+            ),
+            (
+                "AT+HTTPREAD\r",
+                "\r\n+HTTPREAD: 12\r\n{\"accepted\"}\r\nOK\r\n",
+            ),
+            ("AT+HTTPTERM\r", "\r\nOK\r\n"),
+        ];
+        let mut gsm = transport(ModemKind::Sim800, &script);
+        let headers = [("X-A", "1"), ("X-B", "2")];
+        let response = block_on(gsm.http_post("http://example.org/ingest", &headers, b"[]"));
+        assert_eq!(response, Ok((503, "{\"accepted\"}".into())));
+        assert!(gsm.at.io().finished());
+    }
+
+    #[test]
+    fn http_post_fails_without_status() {
+        let script = [
+            ("AT+HTTPTERM\r", "\r\nOK\r\n"),
+            ("AT+HTTPINIT\r", "\r\nOK\r\n"),
+            ("AT+HTTPPARA=\"URL\"", "\r\nOK\r\n"),
+            ("AT+HTTPPARA=\"CONTENT\"", "\r\nOK\r\n"),
+            ("AT+HTTPDATA=2,10000\r", "\r\nDOWNLOAD\r\n"),
+            ("[]", "\r\nOK\r\n"),
+            ("AT+HTTPACTION=1\r", "\r\nOK\r\n\r\n+HTTPACTION: 1\r\n"),
+        ];
+        let mut gsm = transport(ModemKind::Sim7600, &script);
+        let response = block_on(gsm.http_post("http://example.org/ingest", &[], b"[]"));
+        assert_eq!(response, Err(AtError::Error));
+    }
+
+    #[test]
+    fn sim7600_loads_the_trust_anchors_once() {
+        let pem = tls::encode_pem_bundle(&tls::trust_anchors());
+        let download = alloc::format!("AT+CCERTDOWN=\"{}\",{}\r", CA_FILE, pem.len()).leak();
+        let script = [
+            (&*download, "\r\n>"),
+            ("-----END CERTIFICATE-----\n", "\r\nOK\r\n"),
+            ("AT+CSSLCFG=\"sslversion\",0,4\r", "\r\nOK\r\n"),
+            ("AT+CSSLCFG=\"authmode\",0,1\r", "\r\nOK\r\n"),
+            (
+                "AT+CSSLCFG=\"cacert\",0,\"trailsense_ca.pem\"\r",
+                "\r\nOK\r\n",
+            ),
+            ("AT+CSSLCFG=\"enableSNI\",0,1\r", "\r\nOK\r\n"),
+        ];
+        let mut gsm = transport(ModemKind::Sim7600, &script);
+        assert_eq!(block_on(gsm.load_trust_anchors()), Ok(()));
+        assert!(gsm.at.io().finished());
+        assert!(gsm.at.io().written().contains(&pem));
+
+        let written = gsm.at.io().written().len();
+        assert_eq!(block_on(gsm.load_trust_anchors()), Ok(()));
+        assert_eq!(gsm.at.io().written().len(), written);
+    }
+
+    #[test]
+    fn sim7600_https_uses_the_verifying_context() {
+        let script = [
+            ("AT+HTTPTERM\r", "\r\nOK\r\n"),
+            ("AT+HTTPINIT\r", "\r\nOK\r\n"),
+            ("AT+HTTPPARA=\"URL\"", "\r\nOK\r\n"),
+            ("AT+HTTPPARA=\"SSLCFG\",0\r", "\r\nOK\r\n"),
+            ("AT+HTTPPARA=\"CONTENT\"", "\r\nOK\r\n"),
+            ("AT+HTTPDATA=2,10000\r", "\r\nDOWNLOAD\r\n"),
+            ("[]", "\r\nOK\r\n"),
+            (
+                "AT+HTTPACTION=1\r",
+                "\r\nOK\r\n\r\n+HTTPACTION: 1,715,0\r\n",
+            ),
+            ("AT+HTTPREAD\r", "\r\nERROR\r\n"),
+            ("AT+HTTPTERM\r", "\r\nOK\r\n"),
+        ];
+        let mut gsm = transport(ModemKind::Sim7600, &script);
+        let response = block_on(gsm.http_post("https://example.org/ingest", &[], b"[]"));
+        assert_eq!(response.map(|(status, _)| status), Ok(715));
+        assert!(!gsm.at.io().written().contains("HTTPSSL"));
+    }
+
+    #[test]
+    fn status_mapping() {
+        assert_eq!(classify_status(200), HttpStatus::Accepted);
+        assert_eq!(classify_status(204), HttpStatus::Accepted);
+        assert_eq!(classify_status(400), HttpStatus::Rejected);
+        assert_eq!(classify_status(401), HttpStatus::Rejected);
+        assert_eq!(classify_status(500), HttpStatus::Retryable);
+        assert_eq!(classify_status(601), HttpStatus::Retryable);
+        assert_eq!(classify_status(603), HttpStatus::Retryable);
+        assert_eq!(classify_status(700), HttpStatus::Rejected);
+        assert_eq!(classify_status(715), HttpStatus::Unverified);
+        assert_eq!(classify_status(719), HttpStatus::Unverified);
+    }
+}
@@ -0,0 +1,69 @@
+extern crate alloc;
+use crate::{
+    config,
+    network::types::{ConnectionOutcome, IngestResponse, PackageDto, SendDataOutcome},
+    packages::entity::PackageEntity,
+};
+use alloc::vec::Vec;
+use log::{info, warn};
+pub mod auth;
+pub mod failover;
+pub mod gsm;
+pub mod tls;
+pub mod types;
+
+#[allow(async_fn_in_trait)]
+pub trait UplinkTransport {
+    async fn send_data(&mut self, packages: &[PackageEntity]) -> SendDataOutcome;
+    async fn ensure_connected(&mut self) -> ConnectionOutcome;
+}
+
+/// Serializes the packages into the JSON body expected by `{api_url}/ingest`, shared by all transports.
+pub fn encode_payload(packages: &[PackageEntity]) -> Result<Vec<u8>, serde_json::Error> {
+    let config = config::get();
+    let payload: Vec<PackageDto<'_>> = packages
+        .iter()
+        .map(|p| PackageDto::new(p, &config.device_id))
+        .inspect(|dto| info!("Package: {:?}", dto))
+        .collect();
+
+    serde_json::to_vec(&payload)
+}
+
+/// Number of packages from the front of `packages` that fit into one request of at most
+/// `max_entries` packages and `max_bytes` of JSON body. A single oversized package is still sent alone.
+pub fn chunk_len(packages: &[PackageEntity], max_entries: usize, max_bytes: usize) -> usize {
+    let config = config::get();
+    // Opening and closing bracket of the JSON array.
+    let mut size = 2;
+    let mut len = 0;
+    for p in packages.iter().take(max_entries) {
+        let dto = PackageDto::new(p, &config.device_id);
+        let entry_size = serde_json::to_vec(&dto).map_or(max_bytes, |v| v.len()) + 1;
+        if len > 0 && size + entry_size > max_bytes {
+            break;
+        }
+        if len == 0 && size + entry_size > max_bytes {
+            warn!(
+                "Package of {} bytes exceeds chunk limit, sending alone",
+                entry_size
+            );
+            return 1;
+        }
+        size += entry_size;
+        len += 1;
+    }
+    len
+}
+
+/// Sequence numbers acknowledged by a 2xx ingest response.
+/// A body without an `accepted` list (older backends) acknowledges everything that was sent.
+pub fn acknowledged_seqs(packages: &[PackageEntity], body: &str) -> Vec<u32> {
+    match serde_json::from_str::<IngestResponse>(body) {
+        Ok(response) => response.accepted,
+        Err(_) => {
+            warn!("Ingest response has no accepted list, acknowledging all packages");
+            packages.iter().map(|p| p.seq).collect()
+        }
+    }
+}
@@ -0,0 +1,210 @@
+extern crate alloc;
+use alloc::{string::String, vec::Vec};
+
+use base64::Engine;
+
+use crate::config;
+
+/// Roots of the default ingest server, whose certificate is issued by Let's Encrypt.
+/// ISRG Root X1 anchors its RSA chains and ISRG Root X2 its ECDSA chains.
+pub const DEFAULT_CA_PEM: &str = include_str!("default_ca.pem");
+
+/// DER certificates the ingest server chain may lead to, from the `api_ca_pem` setting or
+/// `DEFAULT_CA_PEM` when it is empty. Pinning works by configuring the server's own (self-signed) certificate.
+pub fn trust_anchors() -> Vec<Vec<u8>> {
+    let config = config::get();
+    let pem = if config.api_ca_pem.trim().is_empty() {
+        DEFAULT_CA_PEM
+    } else {
+        &config.api_ca_pem
+    };
+    decode_pem_bundle(pem).unwrap_or_default()
+}
+
+/// Whether this build may upload plain HTTP or over TLS without verifying the server, see the `insecure-tls` feature.
+pub const ALLOW_UNVERIFIED: bool = cfg!(feature = "insecure-tls");
+
+/// Whether this build may upload to `url`, plain `http://` needs `ALLOW_UNVERIFIED`.
+pub fn scheme_allowed(url: &str) -> bool {
+    url.starts_with("https://") || (ALLOW_UNVERIFIED && url.starts_with("http://"))
+}
+
+/// An upload URL split into what connecting and verifying the server need.
+#[derive(Debug, PartialEq)]
+pub struct Endpoint<'a> {
+    pub https: bool,
+    pub host: &'a str,
+    pub port: u16,
+    pub path: &'a str,
+}
+
+impl<'a> Endpoint<'a> {
+    /// `None` for other schemes, an empty host or an invalid port.
+    pub fn parse(url: &'a str) -> Option<Self> {
+        let (https, rest) = if let Some(rest) = url.strip_prefix("https://") {
+            (true, rest)
+        } else {
+            (false, url.strip_prefix("http://")?)
+        };
+        let (authority, path) = rest.find('/').map_or((rest, "/"), |at| rest.split_at(at));
+        let (host, port) = match authority.split_once(':') {
+            Some((host, port)) => (host, port.parse().ok()?),
+            None => (authority, if https { 443 } else { 80 }),
+        };
+        if host.is_empty() {
+            return None;
+        }
+        Some(Endpoint {
+            https,
+            host,
+            port,
+            path,
+        })
+    }
+}
+
+/// Encodes DER certificates as one PEM bundle, the format modems take their trust store in.
+pub fn encode_pem_bundle(anchors: &[Vec<u8>]) -> String {
+    let mut pem = String::new();
+    for der in anchors {
+        let body = base64::engine::general_purpose::STANDARD.encode(der);
+        pem.push_str("-----BEGIN CERTIFICATE-----\n");
+        for line in body.as_bytes().chunks(64) {
+            // Base64 output is ASCII, so every chunk is valid UTF-8.
+            pem.push_str(core::str::from_utf8(line).unwrap_or_default());
+            pem.push('\n');
+        }
+        pem.push_str("-----END CERTIFICATE-----\n");
+    }
+    pem
+}
+
+/// Decodes every certificate of a PEM bundle, a bare base64 body without the armor lines works as well.
+/// `None` when any of them is not valid base64.
+pub fn decode_pem_bundle(pem: &str) -> Option<Vec<Vec<u8>>> {
+    const BEGIN: &str = "-----BEGIN CERTIFICATE-----";
+    const END: &str = "-----END CERTIFICATE-----";
+
+    if pem.trim().is_empty() {
+        return Some(Vec::new());
+    }
+    if !pem.contains(BEGIN) {
+        return decode_base64(pem).map(|der| alloc::vec![der]);
+    }
+    pem.split(BEGIN)
+        .skip(1)
+        .map(|block| decode_base64(block.split_once(END)?.0))
+        .collect()
+}
+
+fn decode_base64(body: &str) -> Option<Vec<u8>> {
+    let body: String = body.chars().filter(|c| !c.is_whitespace()).collect();
+    base64::engine::general_purpose::STANDARD
+        .decode(body)
+        .ok()
+        .filter(|der| !der.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIRST: &str = "MIIBszCCAVmgAwIBAgIUQ2Fm";
+    const SECOND: &str = "MIIB2TCCAX+gAwIBAgIUZm9v";
+
+    fn armored(body: &str) -> String {
+        alloc::format!("-----BEGIN CERTIFICATE-----\n{body}\n-----END CERTIFICATE-----\n")
+    }
+
+    fn der(body: &str) -> Vec<u8> {
+        base64::engine::general_purpose::STANDARD
+            .decode(body)
+            .unwrap()
+    }
+
+    #[test]
+    fn decodes_every_certificate_of_a_bundle() {
+        let bundle = armored(FIRST) + &armored(SECOND);
+        assert_eq!(
+            decode_pem_bundle(&bundle),
+            Some(alloc::vec![der(FIRST), der(SECOND)])
+        );
+    }
+
+    #[test]
+    fn decodes_a_bare_body_and_wrapped_lines() {
+        assert_eq!(decode_pem_bundle(FIRST), Some(alloc::vec![der(FIRST)]));
+        let wrapped = armored(&alloc::format!("{}\r\n{}", &FIRST[..12], &FIRST[12..]));
+        assert_eq!(decode_pem_bundle(&wrapped), Some(alloc::vec![der(FIRST)]));
+    }
+
+    #[test]
+    fn empty_setting_has_no_anchors() {
+        assert_eq!(decode_pem_bundle(""), Some(Vec::new()));
+        assert_eq!(decode_pem_bundle(" \n"), Some(Vec::new()));
+    }
+
+    #[test]
+    fn default_bundle_holds_both_roots() {
+        let anchors = decode_pem_bundle(DEFAULT_CA_PEM).unwrap();
+        assert_eq!(anchors.len(), 2);
+        assert_eq!(
+            decode_pem_bundle(&encode_pem_bundle(&anchors)),
+            Some(anchors)
+        );
+    }
+
+    #[test]
+    fn encoded_bundle_wraps_lines() {
+        let pem = encode_pem_bundle(&[alloc::vec![0xab; 100]]);
+        let lines: Vec<&str> = pem.lines().collect();
+        assert_eq!(lines.len(), 5);
+        assert_eq!(lines[1].len(), 64);
+        assert_eq!(
+            decode_pem_bundle(&pem),
+            Some(alloc::vec![alloc::vec![0xab; 100]])
+        );
+    }
+
+    #[test]
+    fn endpoint_splits_the_url() {
+        assert_eq!(
+            Endpoint::parse("https://api.example.org/ingest"),
+            Some(Endpoint {
+                https: true,
+                host: "api.example.org",
+                port: 443,
+                path: "/ingest",
+            })
+        );
+        assert_eq!(
+            Endpoint::parse("http://10.0.0.2:8080/v1/ingest"),
+            Some(Endpoint {
+                https: false,
+                host: "10.0.0.2",
+                port: 8080,
+                path: "/v1/ingest",
+            })
+        );
+        assert_eq!(Endpoint::parse("https://example.org").unwrap().path, "/");
+        assert_eq!(Endpoint::parse("ftp://example.org/ingest"), None);
+        assert_eq!(Endpoint::parse("https:///ingest"), None);
+        assert_eq!(Endpoint::parse("https://example.org:port/ingest"), None);
+    }
+
+    #[test]
+    fn plain_http_needs_the_insecure_build() {
+        assert!(scheme_allowed("https://example.org"));
+        assert_eq!(scheme_allowed("http://example.org"), ALLOW_UNVERIFIED);
+        assert!(!scheme_allowed("ftp://example.org"));
+    }
+
+    #[test]
+    fn rejects_a_bundle_with_a_broken_certificate() {
+        let bundle = armored(FIRST) + &armored("not base64!");
+        assert_eq!(decode_pem_bundle(&bundle), None);
+        let unterminated = armored(FIRST) + "-----BEGIN CERTIFICATE-----\n" + SECOND;
+        assert_eq!(decode_pem_bundle(&unterminated), None);
+        assert_eq!(decode_pem_bundle(&armored("")), None);
+    }
+}
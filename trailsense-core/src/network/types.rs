@@ -0,0 +1,83 @@
+extern crate alloc;
+use alloc::vec::Vec;
+
+use crate::{clock, packages::entity::PackageEntity, probes::rssi::RSSI_BINS};
+
+#[derive(serde::Serialize, Debug)]
+pub struct PackageDto<'a> {
+    seq: u32,
+    boot_id: u32,
+    age_in_seconds: u64,
+    duration_in_seconds: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    window_start: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    window_end: Option<u64>,
+    count: u32,
+    /// Partial counts, `global_count + randomized_count == count`.
+    global_count: u32,
+    randomized_count: u32,
+    /// Milliseconds of the window the sniffer was listening, less than the duration means a partial window.
+    sniffed_ms: u64,
+    frames_seen: u32,
+    fingerprints_dropped: u32,
+    /// Probe requests per 10 dB bin from -100 dBm up, for calibrating the RSSI band to the counting zone.
+    rssi_histogram: [u32; RSSI_BINS],
+    rssi_gated: u32,
+    node_id: &'a str,
+}
+
+impl<'a> PackageDto<'a> {
+    /// Window bounds are absolute Unix seconds. Bounds not known when the window closed are
+    /// back-computed from the age and span once the clock is known, otherwise only the relative age is sent.
+    pub fn new(package: &PackageEntity, node_id: &'a str) -> Self {
+        let window_end = package.ended_at_unix.or_else(|| {
+            clock::unix_now_secs().map(|now| now.saturating_sub(package.age_in_seconds))
+        });
+        let window_start = package
+            .started_at_unix
+            .or_else(|| window_end.map(|end| end.saturating_sub(package.span_secs)));
+        PackageDto {
+            seq: package.seq,
+            boot_id: package.boot_id,
+            age_in_seconds: package.age_in_seconds,
+            duration_in_seconds: package.span_secs,
+            window_start,
+            window_end,
+            count: package.count,
+            global_count: package.global_count,
+            randomized_count: package.randomized_count,
+            sniffed_ms: package.sniffed_ms,
+            frames_seen: package.frames_seen,
+            fingerprints_dropped: package.fingerprints_dropped,
+            rssi_histogram: package.rssi_histogram,
+            rssi_gated: package.rssi_gated,
+            node_id,
+        }
+    }
+}
+
+/// Body of a successful ingest response, `accepted` lists the `seq` of every package the backend stored
+/// (including ones it already had from an earlier retry).
+#[derive(serde::Deserialize, Debug)]
+pub struct IngestResponse {
+    pub accepted: Vec<u32>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SendDataOutcome {
+    /// Carries the acknowledged package sequence numbers.
+    Success(Vec<u32>),
+    RetryableFailure,
+    FatalFailure,
+    BackoffRequired,
+    /// The server did not present a certificate chaining to the configured trust anchor.
+    TlsVerificationFailed,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ConnectionOutcome {
+    Connected,
+    Disconnected,
+    Failure,
+}
@@ -0,0 +1,260 @@
+use crate::packages::entity::PackageEntity;
+
+#[derive(Clone, Copy, Debug)]
+pub struct EvictionConfig {
+    /// Drop zero-count packages from the older half before merging anything.
+    pub drop_zero_counts: bool,
+    /// Buckets are never merged beyond this span, past that the oldest package is dropped.
+    pub max_bucket_span_secs: u64,
+}
+
+impl EvictionConfig {
+    pub const DEFAULT: Self = Self {
+        drop_zero_counts: false,
+        max_bucket_span_secs: 6 * 60 * 60,
+    };
+}
+
+impl Default for EvictionConfig {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Eviction {
+    /// Remove the package at the index.
+    Drop(usize),
+    /// Merge the package at the index with the next (newer) one.
+    Merge(usize),
+}
+
+/// # Plan Eviction
+///
+/// Picks what to give up when the buffer is full. Only the older half is touched, so recent periods keep full resolution.
+/// Among adjacent pairs the one with the smallest combined span is merged (oldest first on ties),
+/// which coarsens the history evenly instead of losing the oldest data outright.
+/// Packages that were already sent are never merged, the server may hold them and would count them twice.
+pub fn plan_eviction(packages: &[PackageEntity], config: &EvictionConfig) -> Eviction {
+    let older = packages.len() / 2;
+
+    if config.drop_zero_counts
+        && let Some(index) = packages[..older].iter().position(|p| p.count == 0)
+    {
+        return Eviction::Drop(index);
+    }
+
+    let mut best: Option<(usize, u64)> = None;
+    for index in 0..older.min(packages.len().saturating_sub(1)) {
+        let older_package = &packages[index];
+        let newer_package = &packages[index + 1];
+        if older_package.sent || newer_package.sent {
+            continue;
+        }
+        let span = merged_span_secs(
+            older_package.created_at_us,
+            older_package.span_secs,
+            newer_package.created_at_us,
+        );
+        if span > config.max_bucket_span_secs {
+            continue;
+        }
+        if best.is_none_or(|(_, best_span)| span < best_span) {
+            best = Some((index, span));
+        }
+    }
+
+    match best {
+        Some((index, _)) => Eviction::Merge(index),
+        None => Eviction::Drop(0),
+    }
+}
+
+pub fn merged_histogram<const N: usize>(older: &[u32; N], newer: &[u32; N]) -> [u32; N] {
+    core::array::from_fn(|i| older[i].saturating_add(newer[i]))
+}
+
+/// Span of a bucket covering both packages, from the start of the older to the end of the newer one.
+/// Packages are stamped at the end of their window, so the start of the older one is `created_at - span`.
+pub fn merged_span_secs(
+    older_created_at_us: u64,
+    older_span_secs: u64,
+    newer_created_at_us: u64,
+) -> u64 {
+    newer_created_at_us.saturating_sub(older_created_at_us) / 1_000_000 + older_span_secs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::probes::rssi::RSSI_BINS;
+    use embassy_time::Instant;
+
+    /// Back to back windows with the given spans and counts, oldest first.
+    fn packages(windows: &[(u64, u32)]) -> Vec<PackageEntity> {
+        let mut end_us = 0;
+        windows
+            .iter()
+            .enumerate()
+            .map(|(seq, &(span_secs, count))| {
+                end_us += span_secs * 1_000_000;
+                PackageEntity {
+                    seq: seq as u32,
+                    boot_id: 1,
+                    count,
+                    global_count: count,
+                    randomized_count: 0,
+                    age_in_seconds: 0,
+                    last_seen: Instant::from_ticks(0),
+                    created_at_us: end_us,
+                    span_secs,
+                    started_at_unix: None,
+                    ended_at_unix: None,
+                    sniffed_ms: 0,
+                    frames_seen: 0,
+                    fingerprints_dropped: 0,
+                    rssi_histogram: [0; RSSI_BINS],
+                    rssi_gated: 0,
+                    sent: false,
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn merges_the_adjacent_pair_with_the_smallest_combined_span() {
+        let buffer = packages(&[
+            (600, 1),
+            (60, 1),
+            (60, 1),
+            (120, 1),
+            (60, 1),
+            (60, 1),
+            (60, 1),
+            (60, 1),
+        ]);
+        assert_eq!(
+            plan_eviction(&buffer, &EvictionConfig::DEFAULT),
+            Eviction::Merge(1)
+        );
+    }
+
+    #[test]
+    fn picks_the_oldest_pair_on_ties() {
+        let buffer = packages(&[(60, 1); 8]);
+        assert_eq!(
+            plan_eviction(&buffer, &EvictionConfig::DEFAULT),
+            Eviction::Merge(0)
+        );
+    }
+
+    #[test]
+    fn only_merges_pairs_starting_in_the_older_half() {
+        // The newest windows are the shortest, still only pairs starting in the older half are merged.
+        let buffer = packages(&[
+            (600, 1),
+            (600, 1),
+            (300, 1),
+            (600, 1),
+            (10, 1),
+            (10, 1),
+            (10, 1),
+            (10, 1),
+        ]);
+        assert_eq!(
+            plan_eviction(&buffer, &EvictionConfig::DEFAULT),
+            Eviction::Merge(3)
+        );
+    }
+
+    #[test]
+    fn never_merges_sent_packages() {
+        let mut buffer = packages(&[(60, 1); 8]);
+        buffer[1].sent = true;
+        assert_eq!(
+            plan_eviction(&buffer, &EvictionConfig::DEFAULT),
+            Eviction::Merge(2)
+        );
+
+        for package in &mut buffer {
+            package.sent = true;
+        }
+        assert_eq!(
+            plan_eviction(&buffer, &EvictionConfig::DEFAULT),
+            Eviction::Drop(0)
+        );
+    }
+
+    #[test]
+    fn drops_zero_counts_from_the_older_half_first_when_enabled() {
+        let buffer = packages(&[
+            (60, 1),
+            (60, 1),
+            (60, 0),
+            (60, 1),
+            (60, 0),
+            (60, 1),
+            (60, 1),
+            (60, 1),
+        ]);
+        let config = EvictionConfig {
+            drop_zero_counts: true,
+            ..EvictionConfig::DEFAULT
+        };
+        assert_eq!(plan_eviction(&buffer, &config), Eviction::Drop(2));
+        assert_eq!(
+            plan_eviction(&buffer, &EvictionConfig::DEFAULT),
+            Eviction::Merge(0)
+        );
+
+        // A zero count in the newer half is kept.
+        let buffer = packages(&[
+            (60, 1),
+            (60, 1),
+            (60, 1),
+            (60, 1),
+            (60, 0),
+            (60, 1),
+            (60, 1),
+            (60, 1),
+        ]);
+        assert_eq!(plan_eviction(&buffer, &config), Eviction::Merge(0));
+    }
+
+    #[test]
+    fn never_merges_beyond_the_maximum_bucket_span() {
+        let config = EvictionConfig {
+            max_bucket_span_secs: 3600,
+            ..EvictionConfig::DEFAULT
+        };
+        let buffer = packages(&[
+            (3000, 1),
+            (3000, 1),
+            (500, 1),
+            (3000, 1),
+            (700, 1),
+            (60, 1),
+            (60, 1),
+            (60, 1),
+        ]);
+        assert_eq!(plan_eviction(&buffer, &config), Eviction::Merge(1));
+
+        let buffer = packages(&[
+            (3000, 1),
+            (3000, 1),
+            (3000, 1),
+            (3000, 1),
+            (700, 1),
+            (60, 1),
+            (60, 1),
+            (60, 1),
+        ]);
+        assert_eq!(plan_eviction(&buffer, &config), Eviction::Drop(0));
+    }
+
+    #[test]
+    fn merged_span_covers_both_windows_and_the_gap_between_them() {
+        // Older window 10:00-10:05, newer one ends at 10:20.
+        assert_eq!(merged_span_secs(300_000_000, 300, 1_200_000_000), 20 * 60);
+    }
+}
@@ -0,0 +1,129 @@
+use embassy_time::Instant;
+
+use crate::{
+    clock,
+    packages::flash_log::{LogMeta, StoredPackage},
+    probes::{coverage::WindowStats, rssi::RSSI_BINS},
+};
+
+#[derive(Debug, Clone)]
+pub struct PackageEntity {
+    /// Unique per node together with `boot_id`, lets the backend deduplicate retried uploads.
+    /// Merged buckets take a fresh one, so it does not follow the buffer order.
+    pub seq: u32,
+    pub boot_id: u32,
+    /// Sum of `global_count` and `randomized_count`.
+    pub count: u32,
+    /// Devices with a globally unique MAC, counted exactly.
+    pub global_count: u32,
+    /// Devices with a randomized MAC, estimated from fingerprints.
+    pub randomized_count: u32,
+    pub age_in_seconds: u64,
+    pub last_seen: Instant,
+    pub created_at_us: u64,
+    /// Covered duration ending at creation, grows when older packages are merged in.
+    pub span_secs: u64,
+    /// UTC start of the window in Unix seconds, only set when the clock was synced when it opened.
+    pub started_at_unix: Option<u64>,
+    /// UTC end of the window in Unix seconds, only set when the clock was synced at creation.
+    pub ended_at_unix: Option<u64>,
+    /// Time the sniffer could hear probes during the span.
+    pub sniffed_ms: u64,
+    pub frames_seen: u32,
+    pub fingerprints_dropped: u32,
+    /// Probe requests per RSSI bin.
+    pub rssi_histogram: [u32; RSSI_BINS],
+    pub rssi_gated: u32,
+    /// Handed to a transport at least once, the server may already hold it under `seq`.
+    pub sent: bool,
+}
+
+impl PackageEntity {
+    pub fn new(
+        seq: u32,
+        boot_id: u32,
+        global_count: u32,
+        randomized_count: u32,
+        started: Instant,
+        created_at_us: u64,
+        stats: WindowStats,
+    ) -> Self {
+        Self {
+            seq,
+            boot_id,
+            count: global_count.saturating_add(randomized_count),
+            global_count,
+            randomized_count,
+            age_in_seconds: 0,
+            last_seen: Instant::now(),
+            created_at_us,
+            span_secs: started.elapsed().as_secs(),
+            started_at_unix: clock::unix_us_at(started).map(|us| us / 1_000_000),
+            ended_at_unix: clock::unix_now_secs(),
+            sniffed_ms: stats.sniffed.as_millis(),
+            frames_seen: stats.frames_seen,
+            fingerprints_dropped: stats.fingerprints_dropped,
+            rssi_histogram: stats.rssi_histogram,
+            rssi_gated: stats.rssi_gated,
+            sent: false,
+        }
+    }
+
+    /// Restores a persisted package, the age and its reference point are not part of the log.
+    pub fn from_stored(stored: StoredPackage, age_in_seconds: u64, last_seen: Instant) -> Self {
+        Self {
+            seq: stored.seq,
+            boot_id: stored.boot_id,
+            count: stored.count,
+            global_count: stored.global_count,
+            randomized_count: stored.randomized_count,
+            age_in_seconds,
+            last_seen,
+            created_at_us: stored.created_at_us,
+            span_secs: stored.span_secs,
+            started_at_unix: stored.started_at_unix,
+            ended_at_unix: stored.ended_at_unix,
+            sniffed_ms: stored.sniffed_ms,
+            frames_seen: stored.frames_seen,
+            fingerprints_dropped: stored.fingerprints_dropped,
+            rssi_histogram: stored.rssi_histogram,
+            rssi_gated: stored.rssi_gated,
+            sent: stored.sent,
+        }
+    }
+
+    /// Folds the directly preceding package into this one, the same way the log replays it,
+    /// see `StoredPackage::merge_older`.
+    pub fn merge_older(&mut self, older: &PackageEntity, meta: LogMeta) {
+        let mut merged = self.to_stored();
+        merged.merge_older(&older.to_stored(), meta);
+        *self = Self::from_stored(merged, self.age_in_seconds, self.last_seen);
+    }
+
+    pub fn update_age(&mut self) {
+        let now = Instant::now();
+        let delta = now.duration_since(self.last_seen);
+        self.age_in_seconds = self.age_in_seconds.saturating_add(delta.as_secs());
+        self.last_seen = now;
+    }
+
+    pub fn to_stored(&self) -> StoredPackage {
+        StoredPackage {
+            seq: self.seq,
+            boot_id: self.boot_id,
+            count: self.count,
+            global_count: self.global_count,
+            randomized_count: self.randomized_count,
+            created_at_us: self.created_at_us,
+            span_secs: self.span_secs,
+            started_at_unix: self.started_at_unix,
+            ended_at_unix: self.ended_at_unix,
+            sniffed_ms: self.sniffed_ms,
+            frames_seen: self.frames_seen,
+            fingerprints_dropped: self.fingerprints_dropped,
+            rssi_histogram: self.rssi_histogram,
+            rssi_gated: self.rssi_gated,
+            sent: self.sent,
+        }
+    }
+}
@@ -0,0 +1,690 @@
+extern crate alloc;
+use alloc::vec::Vec;
+
+use embedded_storage::nor_flash::NorFlash;
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    packages::downsample::{merged_histogram, merged_span_secs},
+    probes::rssi::RSSI_BINS,
+};
+
+const SECTOR_MAGIC: u32 = 0x5453_504B; // "TSPK"
+const SECTOR_HEADER_LEN: usize = 8;
+const RECORD_HEADER_LEN: usize = 8;
+const MAX_RECORD_LEN: usize = 1024;
+
+/// A package as persisted, `created_at_us` is on the RTC time base so it stays meaningful across resets.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StoredPackage {
+    pub seq: u32,
+    pub boot_id: u32,
+    pub count: u32,
+    pub global_count: u32,
+    pub randomized_count: u32,
+    pub created_at_us: u64,
+    pub span_secs: u64,
+    pub started_at_unix: Option<u64>,
+    pub ended_at_unix: Option<u64>,
+    pub sniffed_ms: u64,
+    pub frames_seen: u32,
+    pub fingerprints_dropped: u32,
+    pub rssi_histogram: [u32; RSSI_BINS],
+    pub rssi_gated: u32,
+    pub sent: bool,
+}
+
+impl StoredPackage {
+    /// Folds the directly preceding package into this one. The bucket is new data for the server,
+    /// so it takes the fresh sequence number of `meta` instead of keeping either one.
+    pub fn merge_older(&mut self, older: &StoredPackage, meta: LogMeta) {
+        self.seq = meta.next_seq;
+        self.boot_id = meta.boot_id;
+        self.span_secs = merged_span_secs(older.created_at_us, older.span_secs, self.created_at_us);
+        self.count = self.count.saturating_add(older.count);
+        self.global_count = self.global_count.saturating_add(older.global_count);
+        self.randomized_count = self.randomized_count.saturating_add(older.randomized_count);
+        self.started_at_unix = older.started_at_unix;
+        self.sniffed_ms = self.sniffed_ms.saturating_add(older.sniffed_ms);
+        self.frames_seen = self.frames_seen.saturating_add(older.frames_seen);
+        self.fingerprints_dropped = self
+            .fingerprints_dropped
+            .saturating_add(older.fingerprints_dropped);
+        self.rssi_histogram = merged_histogram(&older.rssi_histogram, &self.rssi_histogram);
+        self.rssi_gated = self.rssi_gated.saturating_add(older.rssi_gated);
+    }
+}
+
+/// Mutations of the package buffer, replayed in order on mount.
+/// `Checkpoint` ... `Commit` brackets a full snapshot, which makes all older sectors obsolete.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum LogRecord {
+    Checkpoint,
+    Commit,
+    Push(StoredPackage),
+    Meta(LogMeta),
+    Ack(Vec<u32>),
+    Sent(Vec<u32>),
+    Clear,
+    Remove(usize),
+    /// Merges the package at `index` into the next one, which takes the fresh `seq` of `boot_id`.
+    Merged {
+        index: usize,
+        seq: u32,
+        boot_id: u32,
+    },
+}
+
+/// Counters that have to survive reboots independent of the buffered packages.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default)]
+pub struct LogMeta {
+    pub next_seq: u32,
+    pub boot_id: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LogError {
+    Flash,
+    TooSmall,
+    RecordTooLarge,
+    Encode,
+}
+
+#[derive(Clone, Copy)]
+struct Sector {
+    index: u32,
+    seq: u32,
+}
+
+/// # Package Log
+///
+/// Append-only log over a ring of flash sectors.
+/// Each sector starts with a header carrying a sequence number, so the write order survives wrap-around,
+/// and each record is length-prefixed and CRC-checked so torn writes after a power loss are detected and skipped.
+/// Sectors are erased strictly round-robin, which spreads wear evenly over the whole region.
+pub struct PackageLog<F> {
+    flash: F,
+    sectors: u32,
+    head: Sector,
+    offset: u32,
+    base: Sector,
+    meta: LogMeta,
+}
+
+impl<F: NorFlash> PackageLog<F> {
+    /// Mounts the log and replays it, returning the live packages in insertion order.
+    /// An empty or unreadable region is formatted.
+    pub fn mount(mut flash: F) -> Result<(Self, Vec<StoredPackage>), LogError> {
+        let sectors = (flash.capacity() / F::ERASE_SIZE) as u32;
+        if sectors < 4 {
+            return Err(LogError::TooSmall);
+        }
+
+        let mut valid: Vec<Sector> = Vec::new();
+        for index in 0..sectors {
+            if let Some(seq) = read_sector_header(&mut flash, index)? {
+                valid.push(Sector { index, seq });
+            }
+        }
+        valid.sort_unstable_by_key(|s| s.seq);
+
+        // Only the newest run of consecutive sequence numbers belongs to the current log.
+        let mut start = valid.len().saturating_sub(1);
+        while start > 0 && valid[start - 1].seq.wrapping_add(1) == valid[start].seq {
+            start -= 1;
+        }
+        let chain = &valid[start..];
+
+        let Some(&head) = chain.last() else {
+            return Self::format(flash, sectors);
+        };
+
+        let mut log = PackageLog {
+            flash,
+            sectors,
+            head,
+            offset: 0,
+            base: chain[0],
+            meta: LogMeta::default(),
+        };
+
+        // Replay from the newest sector that opens with a completed checkpoint.
+        let mut packages = Vec::new();
+        let mut replay_from = None;
+        for (pos, sector) in chain.iter().enumerate().rev() {
+            if log.opens_with_committed_checkpoint(&chain[pos..])? {
+                replay_from = Some(pos);
+                log.base = *sector;
+                break;
+            }
+        }
+        let Some(replay_from) = replay_from else {
+            warn!("Package log has no complete checkpoint, reformatting");
+            return Self::format(log.flash, sectors);
+        };
+
+        let mut torn = false;
+        let mut replayed = Vec::new();
+        for sector in &chain[replay_from..] {
+            let (records, end, clean) = log.read_sector(sector.index)?;
+            replayed.extend(records);
+            if sector.index == head.index {
+                log.offset = end;
+                torn = !clean;
+            }
+        }
+
+        // A checkpoint cut short by power loss has no `Commit`, replaying it would empty the buffer.
+        // It is dropped and treated like a torn record, so nothing is appended after it.
+        if let Some(last) = replayed
+            .iter()
+            .rposition(|r| matches!(r, LogRecord::Checkpoint))
+            && !replayed[last..]
+                .iter()
+                .any(|r| matches!(r, LogRecord::Commit))
+        {
+            replayed.truncate(last);
+            torn = true;
+        }
+        for record in replayed {
+            log.track_meta(&record);
+            apply(&mut packages, record);
+        }
+
+        // Bytes after a torn record may be partially programmed, continue in a fresh sector.
+        if torn {
+            warn!("Torn record in package log, skipping rest of sector");
+            log.open_next_sector()?;
+            log.base = log.head;
+            log.write_checkpoint(&packages)?;
+        }
+
+        Ok((log, packages))
+    }
+
+    /// Appends `record`. `live` is the buffer state after applying it, used when a new checkpoint is due.
+    pub fn append(&mut self, record: &LogRecord, live: &[StoredPackage]) -> Result<(), LogError> {
+        self.track_meta(record);
+        let bytes = encode_record(record, F::WRITE_SIZE)?;
+        if self.offset as usize + bytes.len() > F::ERASE_SIZE {
+            self.open_next_sector()?;
+
+            // Once half of the ring holds history, snapshot into the new sector so the oldest ones can be reused.
+            // `live` already contains `record`, so it does not need to be written separately.
+            let used = (self.head.index + self.sectors - self.base.index) % self.sectors + 1;
+            if used > self.sectors / 2 {
+                self.base = self.head;
+                return self.write_checkpoint(live);
+            }
+        }
+        self.write_bytes(&bytes)
+    }
+
+    fn format(mut flash: F, sectors: u32) -> Result<(Self, Vec<StoredPackage>), LogError> {
+        let head = Sector { index: 0, seq: 1 };
+        erase_sector(&mut flash, head.index)?;
+        write_sector_header(&mut flash, head)?;
+        let mut log = PackageLog {
+            flash,
+            sectors,
+            head,
+            offset: align_up(SECTOR_HEADER_LEN, F::WRITE_SIZE) as u32,
+            base: head,
+            meta: LogMeta::default(),
+        };
+        log.write_checkpoint(&[])?;
+        Ok((log, Vec::new()))
+    }
+
+    fn write_checkpoint(&mut self, live: &[StoredPackage]) -> Result<(), LogError> {
+        self.write_record(&LogRecord::Checkpoint)?;
+        self.write_record(&LogRecord::Meta(self.meta))?;
+        for package in live {
+            self.write_record(&LogRecord::Push(package.clone()))?;
+        }
+        self.write_record(&LogRecord::Commit)
+    }
+
+    pub fn meta(&self) -> LogMeta {
+        self.meta
+    }
+
+    /// Sequence numbers are also taken from pushes, so they never repeat even when a newer `Meta` was lost.
+    fn track_meta(&mut self, record: &LogRecord) {
+        match record {
+            LogRecord::Meta(meta) => {
+                self.meta = LogMeta {
+                    next_seq: self.meta.next_seq.max(meta.next_seq),
+                    boot_id: meta.boot_id,
+                }
+            }
+            LogRecord::Push(StoredPackage { seq, .. }) | LogRecord::Merged { seq, .. } => {
+                self.meta.next_seq = self.meta.next_seq.max(seq.wrapping_add(1))
+            }
+            _ => {}
+        }
+    }
+
+    fn write_record(&mut self, record: &LogRecord) -> Result<(), LogError> {
+        let bytes = encode_record(record, F::WRITE_SIZE)?;
+        if self.offset as usize + bytes.len() > F::ERASE_SIZE {
+            self.open_next_sector()?;
+        }
+        self.write_bytes(&bytes)
+    }
+
+    fn open_next_sector(&mut self) -> Result<(), LogError> {
+        let next = Sector {
+            index: (self.head.index + 1) % self.sectors,
+            seq: self.head.seq.wrapping_add(1),
+        };
+        if next.index == self.base.index {
+            // Never happens with checkpoints every half ring unless a snapshot outgrows half the region.
+            return Err(LogError::TooSmall);
+        }
+        erase_sector(&mut self.flash, next.index)?;
+        write_sector_header(&mut self.flash, next)?;
+        self.head = next;
+        self.offset = align_up(SECTOR_HEADER_LEN, F::WRITE_SIZE) as u32;
+        Ok(())
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), LogError> {
+        let address = self.head.index * F::ERASE_SIZE as u32 + self.offset;
+        self.flash
+            .write(address, bytes)
+            .map_err(|_| LogError::Flash)?;
+        self.offset += bytes.len() as u32;
+        Ok(())
+    }
+
+    fn opens_with_committed_checkpoint(&mut self, chain: &[Sector]) -> Result<bool, LogError> {
+        let mut first = true;
+        for sector in chain {
+            let (records, _, _) = self.read_sector(sector.index)?;
+            for record in records {
+                match (first, record) {
+                    (true, LogRecord::Checkpoint) => first = false,
+                    (true, _) => return Ok(false),
+                    (false, LogRecord::Commit) => return Ok(true),
+                    (false, LogRecord::Checkpoint) => return Ok(false),
+                    _ => {}
+                }
+            }
+            if first {
+                return Ok(false);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Returns the decodable records, the offset after the last one and whether the sector ended cleanly.
+    fn read_sector(&mut self, index: u32) -> Result<(Vec<LogRecord>, u32, bool), LogError> {
+        let sector_start = index * F::ERASE_SIZE as u32;
+        let mut offset = align_up(SECTOR_HEADER_LEN, F::WRITE_SIZE) as u32;
+        let mut records = Vec::new();
+
+        while offset as usize + RECORD_HEADER_LEN <= F::ERASE_SIZE {
+            let mut header = [0u8; RECORD_HEADER_LEN];
+            read(&mut self.flash, sector_start + offset, &mut header)?;
+            if header.iter().all(|&b| b == 0xFF) {
+                return Ok((records, offset, true));
+            }
+
+            let len = u16::from_le_bytes([header[0], header[1]]);
+            let len_check = u16::from_le_bytes([header[2], header[3]]);
+            let crc = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+            let total = align_up(RECORD_HEADER_LEN + len as usize, F::WRITE_SIZE);
+            if len != !len_check
+                || len as usize > MAX_RECORD_LEN
+                || offset as usize + total > F::ERASE_SIZE
+            {
+                return Ok((records, offset, false));
+            }
+
+            // Reads have to be READ_SIZE aligned, the padding after the payload covers the excess.
+            let mut payload = alloc::vec![0u8; align_up(len as usize, F::READ_SIZE)];
+            read(
+                &mut self.flash,
+                sector_start + offset + RECORD_HEADER_LEN as u32,
+                &mut payload,
+            )?;
+            payload.truncate(len as usize);
+            if crc32fast::hash(&payload) != crc {
+                return Ok((records, offset, false));
+            }
+            match serde_json::from_slice::<LogRecord>(&payload) {
+                Ok(record) => records.push(record),
+                Err(_) => warn!("Skipping undecodable package log record"),
+            }
+            offset += total as u32;
+        }
+        Ok((records, offset, true))
+    }
+}
+
+pub fn apply(packages: &mut Vec<StoredPackage>, record: LogRecord) {
+    match record {
+        LogRecord::Checkpoint | LogRecord::Clear => packages.clear(),
+        LogRecord::Commit | LogRecord::Meta(_) => {}
+        LogRecord::Ack(seqs) => packages.retain(|p| !seqs.contains(&p.seq)),
+        LogRecord::Sent(seqs) => {
+            for package in packages.iter_mut().filter(|p| seqs.contains(&p.seq)) {
+                package.sent = true;
+            }
+        }
+        LogRecord::Push(p) => packages.push(p),
+        LogRecord::Remove(index) => {
+            if index < packages.len() {
+                packages.remove(index);
+            }
+        }
+        LogRecord::Merged {
+            index,
+            seq,
+            boot_id,
+        } => {
+            if index + 1 < packages.len() {
+                let older = packages.remove(index);
+                let meta = LogMeta {
+                    next_seq: seq,
+                    boot_id,
+                };
+                packages[index].merge_older(&older, meta);
+            }
+        }
+    }
+}
+
+fn encode_record(record: &LogRecord, write_size: usize) -> Result<Vec<u8>, LogError> {
+    let payload = serde_json::to_vec(record).map_err(|_| LogError::Encode)?;
+    if payload.len() > MAX_RECORD_LEN {
+        return Err(LogError::RecordTooLarge);
+    }
+    let len = payload.len() as u16;
+    let mut bytes = Vec::with_capacity(align_up(RECORD_HEADER_LEN + payload.len(), write_size));
+    bytes.extend_from_slice(&len.to_le_bytes());
+    bytes.extend_from_slice(&(!len).to_le_bytes());
+    bytes.extend_from_slice(&crc32fast::hash(&payload).to_le_bytes());
+    bytes.extend_from_slice(&payload);
+    bytes.resize(align_up(bytes.len(), write_size), 0xFF);
+    Ok(bytes)
+}
+
+fn read_sector_header<F: NorFlash>(flash: &mut F, index: u32) -> Result<Option<u32>, LogError> {
+    let mut header = [0u8; SECTOR_HEADER_LEN];
+    read(flash, index * F::ERASE_SIZE as u32, &mut header)?;
+    let seq = u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+    let magic = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+    Ok((magic == SECTOR_MAGIC).then_some(seq))
+}
+
+fn write_sector_header<F: NorFlash>(flash: &mut F, sector: Sector) -> Result<(), LogError> {
+    let mut header = alloc::vec![0xFFu8; align_up(SECTOR_HEADER_LEN, F::WRITE_SIZE)];
+    // Magic goes last, a header torn by power loss then never looks valid with a garbled sequence number.
+    header[..4].copy_from_slice(&sector.seq.to_le_bytes());
+    header[4..8].copy_from_slice(&SECTOR_MAGIC.to_le_bytes());
+    flash
+        .write(sector.index * F::ERASE_SIZE as u32, &header)
+        .map_err(|_| LogError::Flash)
+}
+
+fn erase_sector<F: NorFlash>(flash: &mut F, index: u32) -> Result<(), LogError> {
+    let from = index * F::ERASE_SIZE as u32;
+    flash
+        .erase(from, from + F::ERASE_SIZE as u32)
+        .map_err(|_| LogError::Flash)
+}
+
+fn read<F: NorFlash>(flash: &mut F, offset: u32, bytes: &mut [u8]) -> Result<(), LogError> {
+    flash.read(offset, bytes).map_err(|_| LogError::Flash)
+}
+
+fn align_up(value: usize, align: usize) -> usize {
+    value.div_ceil(align) * align
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embedded_storage::nor_flash::{
+        ErrorType, NorFlashErrorKind, ReadNorFlash, check_erase, check_read, check_write,
+    };
+
+    const SECTORS: usize = 8;
+
+    /// NOR flash in RAM: writes can only clear bits and erases are counted per sector.
+    struct MemFlash {
+        data: Vec<u8>,
+        erases: [u32; SECTORS],
+    }
+
+    impl MemFlash {
+        fn new() -> Self {
+            MemFlash {
+                data: alloc::vec![0xFF; SECTORS * 4096],
+                erases: [0; SECTORS],
+            }
+        }
+    }
+
+    impl ErrorType for MemFlash {
+        type Error = NorFlashErrorKind;
+    }
+
+    impl ReadNorFlash for MemFlash {
+        const READ_SIZE: usize = 4;
+
+        fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+            check_read(self, offset, bytes.len())?;
+            let offset = offset as usize;
+            bytes.copy_from_slice(&self.data[offset..offset + bytes.len()]);
+            Ok(())
+        }
+
+        fn capacity(&self) -> usize {
+            self.data.len()
+        }
+    }
+
+    impl NorFlash for MemFlash {
+        const WRITE_SIZE: usize = 4;
+        const ERASE_SIZE: usize = 4096;
+
+        fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+            check_erase(self, from, to)?;
+            self.erases[from as usize / Self::ERASE_SIZE] += 1;
+            self.data[from as usize..to as usize].fill(0xFF);
+            Ok(())
+        }
+
+        fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+            check_write(self, offset, bytes.len())?;
+            for (cell, byte) in self.data[offset as usize..].iter_mut().zip(bytes) {
+                assert_eq!(*cell, 0xFF, "write to unerased flash at {offset}");
+                *cell = *byte;
+            }
+            Ok(())
+        }
+    }
+
+    fn package(seq: u32, count: u32) -> StoredPackage {
+        StoredPackage {
+            seq,
+            boot_id: 1,
+            count,
+            global_count: count,
+            randomized_count: 0,
+            created_at_us: u64::from(seq) * 60_000_000,
+            span_secs: 60,
+            started_at_unix: None,
+            ended_at_unix: None,
+            sniffed_ms: 60_000,
+            frames_seen: count,
+            fingerprints_dropped: 0,
+            rssi_histogram: [count; RSSI_BINS],
+            rssi_gated: 0,
+            sent: false,
+        }
+    }
+
+    /// Applies `record` to `live` and appends it, as the package store does.
+    fn append(
+        log: &mut PackageLog<&mut MemFlash>,
+        live: &mut Vec<StoredPackage>,
+        record: LogRecord,
+    ) {
+        apply(live, record.clone());
+        log.append(&record, live).unwrap();
+    }
+
+    fn seqs(packages: &[StoredPackage]) -> Vec<u32> {
+        packages.iter().map(|p| p.seq).collect()
+    }
+
+    #[test]
+    fn mount_formats_blank_flash() {
+        let mut flash = MemFlash::new();
+        let (log, restored) = PackageLog::mount(&mut flash).unwrap();
+        assert!(restored.is_empty());
+        assert_eq!(log.meta().next_seq, 0);
+        assert!(PackageLog::mount(&mut flash).unwrap().1.is_empty());
+    }
+
+    #[test]
+    fn mount_rejects_tiny_region() {
+        let mut flash = MemFlash::new();
+        flash.data.truncate(3 * 4096);
+        assert!(matches!(
+            PackageLog::mount(&mut flash),
+            Err(LogError::TooSmall)
+        ));
+    }
+
+    #[test]
+    fn replay_restores_packages_and_meta() {
+        let mut flash = MemFlash::new();
+        let mut live = Vec::new();
+        let (mut log, _) = PackageLog::mount(&mut flash).unwrap();
+        append(
+            &mut log,
+            &mut live,
+            LogRecord::Meta(LogMeta {
+                next_seq: 0,
+                boot_id: 7,
+            }),
+        );
+        for seq in 0..6 {
+            append(&mut log, &mut live, LogRecord::Push(package(seq, seq + 1)));
+        }
+        append(&mut log, &mut live, LogRecord::Ack(alloc::vec![0, 3]));
+        append(&mut log, &mut live, LogRecord::Remove(0));
+        let merged = LogRecord::Merged {
+            index: 0,
+            seq: 6,
+            boot_id: 7,
+        };
+        append(&mut log, &mut live, merged);
+
+        let (log, restored) = PackageLog::mount(&mut flash).unwrap();
+        assert_eq!(seqs(&restored), [6, 5]);
+        assert_eq!(restored[0].count, 3 + 5);
+        assert_eq!(restored[0].span_secs, 2 * 60 + 60);
+        assert_eq!(restored[0].rssi_histogram, [3 + 5; RSSI_BINS]);
+        assert_eq!(log.meta().boot_id, 7);
+        assert_eq!(log.meta().next_seq, 7);
+    }
+
+    #[test]
+    fn merged_bucket_replays_with_its_fresh_seq() {
+        let mut flash = MemFlash::new();
+        let mut live = Vec::new();
+        let (mut log, _) = PackageLog::mount(&mut flash).unwrap();
+        for seq in 0..3 {
+            append(&mut log, &mut live, LogRecord::Push(package(seq, 1)));
+        }
+        append(&mut log, &mut live, LogRecord::Sent(alloc::vec![2]));
+        let merged = LogRecord::Merged {
+            index: 0,
+            seq: 3,
+            boot_id: 2,
+        };
+        append(&mut log, &mut live, merged);
+
+        let (log, restored) = PackageLog::mount(&mut flash).unwrap();
+        assert_eq!(seqs(&restored), [3, 2]);
+        assert_eq!((restored[0].boot_id, restored[0].count), (2, 2));
+        assert_eq!(
+            restored.iter().map(|p| p.sent).collect::<Vec<_>>(),
+            [false, true]
+        );
+        // The merge used up seq 3 even though no push carried it.
+        assert_eq!(log.meta().next_seq, 4);
+    }
+
+    #[test]
+    fn wrap_around_keeps_live_packages_and_spreads_erases() {
+        let mut flash = MemFlash::new();
+        let mut live = Vec::new();
+        let mut seq = 0;
+        for _ in 0..20 {
+            let (mut log, restored) = PackageLog::mount(&mut flash).unwrap();
+            assert_eq!(seqs(&restored), seqs(&live));
+            for _ in 0..200 {
+                if live.len() == 32 {
+                    append(&mut log, &mut live, LogRecord::Remove(0));
+                }
+                append(&mut log, &mut live, LogRecord::Push(package(seq, seq)));
+                seq += 1;
+            }
+        }
+
+        let (log, restored) = PackageLog::mount(&mut flash).unwrap();
+        assert_eq!(seqs(&restored), seqs(&live));
+        assert_eq!(log.meta().next_seq, seq);
+        // Many times around the ring, every sector is erased about equally often.
+        let min = *flash.erases.iter().min().unwrap();
+        let max = *flash.erases.iter().max().unwrap();
+        assert!(min > 10, "{:?}", flash.erases);
+        assert!(max - min <= 1, "{:?}", flash.erases);
+    }
+
+    #[test]
+    fn crc_mismatch_drops_the_record_and_everything_after_it() {
+        let mut flash = MemFlash::new();
+        let mut live = Vec::new();
+        let (mut log, _) = PackageLog::mount(&mut flash).unwrap();
+        for seq in 0..2 {
+            append(&mut log, &mut live, LogRecord::Push(package(seq, 1)));
+        }
+        let corrupt_at =
+            log.head.index as usize * 4096 + log.offset as usize + RECORD_HEADER_LEN + 4;
+        for seq in 2..4 {
+            append(&mut log, &mut live, LogRecord::Push(package(seq, 1)));
+        }
+        // A bit error in the payload of the third record, its header still looks intact.
+        flash.data[corrupt_at] &= 0xFE;
+
+        let (mut log, restored) = PackageLog::mount(&mut flash).unwrap();
+        assert_eq!(seqs(&restored), [0, 1]);
+
+        // The rest of the damaged sector is skipped, later records land in a fresh one and survive.
+        let mut live = restored;
+        append(&mut log, &mut live, LogRecord::Push(package(9, 1)));
+        assert_eq!(seqs(&PackageLog::mount(&mut flash).unwrap().1), [0, 1, 9]);
+    }
+
+    #[test]
+    fn uncommitted_checkpoint_is_ignored() {
+        let mut flash = MemFlash::new();
+        let mut live = Vec::new();
+        let (mut log, _) = PackageLog::mount(&mut flash).unwrap();
+        append(&mut log, &mut live, LogRecord::Push(package(0, 1)));
+        // Power lost while writing a snapshot, after its `Checkpoint` but before the `Commit`.
+        log.write_record(&LogRecord::Checkpoint).unwrap();
+        log.write_record(&LogRecord::Push(package(0, 1))).unwrap();
+
+        assert_eq!(seqs(&PackageLog::mount(&mut flash).unwrap().1), [0]);
+    }
+}
@@ -0,0 +1,3 @@
+pub mod downsample;
+pub mod entity;
+pub mod flash_log;
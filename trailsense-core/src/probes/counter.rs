@@ -0,0 +1,354 @@
+extern crate alloc;
+use alloc::{collections::BTreeSet, vec::Vec};
+
+use crate::probes::{
+    fingerprint::Fingerprint,
+    models::{MODEL, MODEL_SIZE},
+    probe_parser::FingerprintMethod,
+};
+
+/// Cost of a differing bit, index 0 is the least significant bit of a fingerprint.
+pub type BitWeights = [f32; Fingerprint::BITS as usize];
+
+/// Mask fingerprints weigh each bit by its classifier's `alpha`, the first classifier ends up in the highest
+/// used bit. Feature fingerprints have no trained weights, every bit costs 1.
+pub fn bit_weights(method: FingerprintMethod) -> BitWeights {
+    match method {
+        FingerprintMethod::Masks => {
+            core::array::from_fn(|bit| match MODEL_SIZE.checked_sub(bit + 1) {
+                Some(classifier) => MODEL[classifier].alpha,
+                None => 0.0,
+            })
+        }
+        FingerprintMethod::Features => [1.0; Fingerprint::BITS as usize],
+    }
+}
+
+/// # Default Threshold
+///
+/// Used when the config sets none. With the shipped alphas every pair of mask bits is within 2.5 and no three
+/// bits are, so the default merges exactly the fingerprints at most two bits apart, like the unweighted
+/// threshold of 2. The weights only matter between the pair sums of 2.18 and 2.47. Bits of a feature
+/// fingerprint are hashes, see `ProbeFeatures::fingerprint`, so only identical ones count as one device.
+pub fn default_threshold(method: FingerprintMethod) -> f32 {
+    match method {
+        FingerprintMethod::Masks => 2.5,
+        FingerprintMethod::Features => 0.0,
+    }
+}
+
+/// Sum of the weights of the bits in which `a` and `b` differ.
+pub fn weighted_distance(a: Fingerprint, b: Fingerprint, weights: &BitWeights) -> f32 {
+    let mut diff = a ^ b;
+    let mut distance = 0.0;
+    while diff != 0 {
+        distance += weights[diff.trailing_zeros() as usize];
+        diff &= diff - 1;
+    }
+    distance
+}
+
+/// # Deduplicate Probes
+///
+/// Counts the fingerprints left after dropping every one within `threshold` weighted distance of an
+/// earlier survivor.
+///
+/// Repeats are dropped up front, a repeat is always within distance 0 of its first occurrence or the
+/// survivor that one matched. The rest look up every fingerprint within `threshold` of themselves in the
+/// survivor set, which is linear in the input as long as that ball is small, and fall back to comparing
+/// with each survivor when it is not. Both give the same survivors as comparing pairwise in input order,
+/// distances are even summed in the same bit order.
+pub fn deduplicate_probes(
+    input_fingerprints: &[Fingerprint],
+    weights: &BitWeights,
+    threshold: f32,
+) -> u32 {
+    // Bits without weight never change a distance, so they are cleared before comparing.
+    let flips: Vec<(Fingerprint, f32)> = weights
+        .iter()
+        .enumerate()
+        .filter(|(_, weight)| **weight > 0.0)
+        .map(|(bit, weight)| (1 << bit, *weight))
+        .collect();
+    let mask = flips.iter().fold(0, |mask, (bit, _)| mask | bit);
+
+    let mut seen = BTreeSet::new();
+    let distinct: Vec<Fingerprint> = input_fingerprints
+        .iter()
+        .map(|fingerprint| fingerprint & mask)
+        .filter(|fingerprint| seen.insert(*fingerprint))
+        .collect();
+
+    if ball_size(&flips, threshold) > distinct.len() {
+        return deduplicate_pairwise(&distinct, weights, threshold);
+    }
+
+    let mut survivors = BTreeSet::new();
+    for fingerprint in distinct {
+        if !ball_contains(&survivors, fingerprint, &flips, 0.0, threshold) {
+            survivors.insert(fingerprint);
+        }
+    }
+    survivors.len() as u32
+}
+
+/// Whether a survivor is reachable from `candidate` by flipping bits of `flips` in ascending order
+/// without the summed weight exceeding `threshold`.
+fn ball_contains(
+    survivors: &BTreeSet<Fingerprint>,
+    candidate: Fingerprint,
+    flips: &[(Fingerprint, f32)],
+    distance: f32,
+    threshold: f32,
+) -> bool {
+    if survivors.contains(&candidate) {
+        return true;
+    }
+    flips.iter().enumerate().any(|(index, &(bit, weight))| {
+        let distance = distance + weight;
+        distance <= threshold
+            && ball_contains(
+                survivors,
+                candidate ^ bit,
+                &flips[index + 1..],
+                distance,
+                threshold,
+            )
+    })
+}
+
+/// Upper bound on the fingerprints within `threshold` of one, from the most bits the cheapest weights allow.
+fn ball_size(flips: &[(Fingerprint, f32)], threshold: f32) -> usize {
+    let mut weights: Vec<f32> = flips.iter().map(|(_, weight)| *weight).collect();
+    weights.sort_by(f32::total_cmp);
+    let mut radius = 0;
+    let mut distance = 0.0;
+    for weight in weights {
+        distance += weight;
+        if distance > threshold {
+            break;
+        }
+        radius += 1;
+    }
+
+    // Sum of the binomial coefficients C(bits, k) up to the radius.
+    let bits = flips.len();
+    let mut size = 1usize;
+    let mut term = 1usize;
+    for k in 1..=radius {
+        term = term.saturating_mul(bits - k + 1) / k;
+        size = size.saturating_add(term);
+    }
+    size
+}
+
+/// Compares every fingerprint with each survivor. The reference the ball lookup is tested against,
+/// and its fallback when the ball would be larger than the input.
+fn deduplicate_pairwise(fingerprints: &[Fingerprint], weights: &BitWeights, threshold: f32) -> u32 {
+    let mut survivors: Vec<Fingerprint> = Vec::new();
+    for &fingerprint in fingerprints {
+        if !is_duplicate(threshold, fingerprint, &survivors, weights) {
+            survivors.push(fingerprint);
+        }
+    }
+    survivors.len() as u32
+}
+
+fn is_duplicate(
+    threshold: f32,
+    input: Fingerprint,
+    survivors: &[Fingerprint],
+    weights: &BitWeights,
+) -> bool {
+    for &s in survivors {
+        if weighted_distance(input, s, weights) <= threshold {
+            return true;
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UNIT: BitWeights = [1.0; Fingerprint::BITS as usize];
+
+    /// Fingerprint with the bits of the given classifiers set.
+    fn classifiers(indices: &[usize]) -> Fingerprint {
+        indices.iter().fold(0, |fingerprint, index| {
+            fingerprint | (1 << (MODEL_SIZE - 1 - index))
+        })
+    }
+
+    #[test]
+    fn mask_weights_follow_the_classifier_order() {
+        let weights = bit_weights(FingerprintMethod::Masks);
+        assert_eq!(weights[MODEL_SIZE - 1], MODEL[0].alpha);
+        assert_eq!(weights[0], MODEL[MODEL_SIZE - 1].alpha);
+        assert_eq!(
+            weighted_distance(classifiers(&[]), classifiers(&[13, 14]), &weights),
+            MODEL[14].alpha + MODEL[13].alpha
+        );
+    }
+
+    #[test]
+    fn default_mask_threshold_merges_every_pair_and_no_triple() {
+        let threshold = default_threshold(FingerprintMethod::Masks);
+        let mut alphas: Vec<f32> = MODEL.iter().map(|c| c.alpha).collect();
+        alphas.sort_by(f32::total_cmp);
+        let heaviest_pair: f32 = alphas[MODEL_SIZE - 2..].iter().sum();
+        let lightest_triple: f32 = alphas[..3].iter().sum();
+        assert!(heaviest_pair <= threshold && threshold < lightest_triple);
+    }
+
+    #[test]
+    fn weighting_changes_nothing_at_the_default_threshold() {
+        let masks = bit_weights(FingerprintMethod::Masks);
+        let sets: [&[Fingerprint]; 3] = [
+            &[
+                classifiers(&[]),
+                classifiers(&[9, 14]),
+                classifiers(&[0, 1, 2]),
+            ],
+            &[
+                classifiers(&[12, 13]),
+                classifiers(&[12]),
+                classifiers(&[3, 4, 5, 6]),
+            ],
+            &[
+                classifiers(&[0]),
+                classifiers(&[1]),
+                classifiers(&[2, 3]),
+                classifiers(&[0, 2, 3]),
+            ],
+        ];
+        for set in sets {
+            assert_eq!(
+                deduplicate_probes(set, &masks, 2.5),
+                deduplicate_probes(set, &UNIT, 2.0)
+            );
+        }
+    }
+
+    #[test]
+    fn weighting_keeps_confident_pairs_apart_between_the_pair_sums() {
+        let masks = bit_weights(FingerprintMethod::Masks);
+        // Classifiers 12 and 13 have the lowest alphas, 9 and 14 the highest.
+        let uncertain_pair = [classifiers(&[]), classifiers(&[12, 13])];
+        let confident_pair = [classifiers(&[]), classifiers(&[9, 14])];
+
+        assert_eq!(deduplicate_probes(&uncertain_pair, &masks, 2.3), 1);
+        assert_eq!(deduplicate_probes(&confident_pair, &masks, 2.3), 2);
+        // Unweighted both pairs are two bits apart, they merge or stay apart together.
+        for threshold in [1.9, 2.3] {
+            assert_eq!(
+                deduplicate_probes(&uncertain_pair, &UNIT, threshold),
+                deduplicate_probes(&confident_pair, &UNIT, threshold)
+            );
+        }
+    }
+
+    /// Xorshift, enough to spread test fingerprints without a dependency.
+    fn random(seed: &mut u64) -> u64 {
+        *seed ^= *seed << 13;
+        *seed ^= *seed >> 7;
+        *seed ^= *seed << 17;
+        *seed
+    }
+
+    /// `len` fingerprints drawn from a pool of `distinct` ones, some with a bit flipped,
+    /// so the set has repeats and near misses like a real window.
+    fn window(seed: &mut u64, len: usize, distinct: usize) -> Vec<Fingerprint> {
+        let pool: Vec<Fingerprint> = (0..distinct).map(|_| random(seed) as Fingerprint).collect();
+        (0..len)
+            .map(|_| {
+                let r = random(seed);
+                let fingerprint = pool[r as usize % pool.len()];
+                if r & 0x300 == 0 {
+                    fingerprint ^ (1 << ((r >> 20) % Fingerprint::BITS as u64))
+                } else {
+                    fingerprint
+                }
+            })
+            .collect()
+    }
+
+    fn weight_sets() -> [BitWeights; 3] {
+        let mut partial = UNIT;
+        partial[MODEL_SIZE / 2..].fill(0.0);
+        [bit_weights(FingerprintMethod::Masks), UNIT, partial]
+    }
+
+    fn assert_matches_pairwise(input: &[Fingerprint], weights: &BitWeights, threshold: f32) {
+        assert_eq!(
+            deduplicate_probes(input, weights, threshold),
+            deduplicate_pairwise(input, weights, threshold),
+            "threshold {threshold}, {} fingerprints",
+            input.len()
+        );
+    }
+
+    #[test]
+    fn matches_pairwise_on_fixed_sets() {
+        let sets: [&[Fingerprint]; 5] = [
+            &[],
+            &[0x1234],
+            &[0x1234, 0x1234, 0x1234],
+            &[0, 0b1, 0b11, 0b111, 0b1111, 0b1, 0],
+            &[
+                0xffff, 0x7fff, 0x0000, 0x8000, 0xfffe, 0x00ff, 0xff00, 0x00ff,
+            ],
+        ];
+        for weights in weight_sets() {
+            for threshold in [0.0, 1.0, 2.5, 3.6, 8.0, 100.0] {
+                for set in sets {
+                    assert_matches_pairwise(set, &weights, threshold);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn matches_pairwise_on_random_windows() {
+        let mut seed = 0x2545_f491_4f6c_dd1d;
+        for weights in weight_sets() {
+            for threshold in [0.0, 2.3, 2.5, 3.6, 5.0, 100.0] {
+                for len in [1, 50, 500, 2048] {
+                    let distinct = (random(&mut seed) % 400 + 1) as usize;
+                    let input = window(&mut seed, len, distinct);
+                    assert_matches_pairwise(&input, &weights, threshold);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn large_thresholds_take_the_pairwise_fallback() {
+        let weights = bit_weights(FingerprintMethod::Masks);
+        let flips: Vec<(Fingerprint, f32)> = weights
+            .iter()
+            .enumerate()
+            .map(|(bit, weight)| (1 << bit, *weight))
+            .collect();
+        let mut seed = 7;
+        let input = window(&mut seed, 200, 100);
+        assert!(ball_size(&flips, 8.0) > input.len());
+        assert!(ball_size(&flips, 2.5) < input.len());
+        for threshold in [8.0, 100.0] {
+            assert_matches_pairwise(&input, &weights, threshold);
+        }
+        // Everything is within the sum of all weights of everything else.
+        assert_eq!(deduplicate_probes(&input, &weights, 100.0), 1);
+    }
+
+    #[test]
+    fn feature_fingerprints_only_merge_when_identical_by_default() {
+        let threshold = default_threshold(FingerprintMethod::Features);
+        let weights = bit_weights(FingerprintMethod::Features);
+        assert_eq!(
+            deduplicate_probes(&[0b1, 0b1, 0b11], &weights, threshold),
+            2
+        );
+    }
+}
@@ -0,0 +1,183 @@
+use core::cell::RefCell;
+
+use embassy_sync::blocking_mutex::{Mutex, raw::CriticalSectionRawMutex};
+use embassy_time::{Duration, Instant};
+use log::info;
+use serde::Serialize;
+
+use crate::probes::rssi::{self, RSSI_BINS};
+
+/// 2.4 GHz channels 1 to 14.
+pub const CHANNELS: usize = 14;
+
+/// What the sniffer did during one counting window, so partial windows can be told from quiet ones.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct WindowStats {
+    pub sniffed: Duration,
+    pub frames_seen: u32,
+    /// Fingerprints lost because the fingerprint store was full.
+    pub fingerprints_dropped: u32,
+    /// Probe requests per RSSI bin, see `rssi::bin`.
+    pub rssi_histogram: [u32; RSSI_BINS],
+    /// Probe requests outside the configured RSSI band, so not counted.
+    pub rssi_gated: u32,
+}
+
+/// Frames heard per channel since boot, index 0 is channel 1. With equal dwell times across a hop plan
+/// the counts compare directly, which shows where a site's probe traffic is.
+#[derive(Serialize, Clone, Copy, Debug)]
+pub struct ChannelStats {
+    pub frames: [u32; CHANNELS],
+    pub probe_requests: [u32; CHANNELS],
+}
+
+/// # Sniffer Coverage
+///
+/// Measures how long the sniffer could actually hear probes during a window.
+/// That needs promiscuous mode (set by the Wi-Fi manager) and a started radio that is not scanning
+/// (set by the connect task).
+struct Coverage {
+    sniffing: bool,
+    radio_available: bool,
+    active_since: Option<Instant>,
+    accumulated: Duration,
+    frames_seen: u32,
+    fingerprints_dropped: u32,
+    rssi_histogram: [u32; RSSI_BINS],
+    rssi_gated: u32,
+    channels: ChannelStats,
+}
+
+impl Coverage {
+    const fn new() -> Self {
+        Coverage {
+            sniffing: false,
+            radio_available: false,
+            active_since: None,
+            accumulated: Duration::from_ticks(0),
+            frames_seen: 0,
+            fingerprints_dropped: 0,
+            rssi_histogram: [0; RSSI_BINS],
+            rssi_gated: 0,
+            channels: ChannelStats {
+                frames: [0; CHANNELS],
+                probe_requests: [0; CHANNELS],
+            },
+        }
+    }
+
+    fn update(&mut self, now: Instant) {
+        if let Some(since) = self.active_since.take() {
+            self.accumulated += now.saturating_duration_since(since);
+        }
+        if self.sniffing && self.radio_available {
+            self.active_since = Some(now);
+        }
+    }
+}
+
+static COVERAGE: Mutex<CriticalSectionRawMutex, RefCell<Coverage>> =
+    Mutex::new(RefCell::new(Coverage::new()));
+
+fn modify(f: impl FnOnce(&mut Coverage)) {
+    COVERAGE.lock(|c| {
+        let mut coverage = c.borrow_mut();
+        f(&mut coverage);
+        coverage.update(Instant::now());
+    });
+}
+
+fn channel_index(channel: u8) -> Option<usize> {
+    let index = usize::from(channel).checked_sub(1)?;
+    (index < CHANNELS).then_some(index)
+}
+
+pub fn set_sniffing(enabled: bool) {
+    modify(|c| c.sniffing = enabled);
+}
+
+pub fn set_radio_available(available: bool) {
+    modify(|c| c.radio_available = available);
+}
+
+/// Called from the receive callback for every frame, so it only bumps counters.
+pub fn record_frame(channel: u8) {
+    COVERAGE.lock(|c| {
+        let mut coverage = c.borrow_mut();
+        coverage.frames_seen = coverage.frames_seen.saturating_add(1);
+        if let Some(index) = channel_index(channel) {
+            coverage.channels.frames[index] = coverage.channels.frames[index].saturating_add(1);
+        }
+    });
+}
+
+pub fn record_probe_request(channel: u8) {
+    if let Some(index) = channel_index(channel) {
+        COVERAGE.lock(|c| {
+            let probes = &mut c.borrow_mut().channels.probe_requests[index];
+            *probes = probes.saturating_add(1);
+        });
+    }
+}
+
+pub fn record_fingerprint_dropped() {
+    COVERAGE.lock(|c| {
+        let mut coverage = c.borrow_mut();
+        coverage.fingerprints_dropped = coverage.fingerprints_dropped.saturating_add(1);
+    });
+}
+
+pub fn record_rssi(rssi: i8, admitted: bool) {
+    COVERAGE.lock(|c| {
+        let mut coverage = c.borrow_mut();
+        let bin = &mut coverage.rssi_histogram[rssi::bin(rssi)];
+        *bin = bin.saturating_add(1);
+        if !admitted {
+            coverage.rssi_gated = coverage.rssi_gated.saturating_add(1);
+        }
+    });
+}
+
+pub fn channel_stats() -> ChannelStats {
+    COVERAGE.lock(|c| c.borrow().channels)
+}
+
+pub fn log_channel_stats() {
+    let stats = channel_stats();
+    for (index, (frames, probes)) in stats
+        .frames
+        .iter()
+        .zip(stats.probe_requests.iter())
+        .enumerate()
+    {
+        if *frames > 0 {
+            info!(
+                "Channel {:>2}: {} frames, {} probe requests",
+                index + 1,
+                frames,
+                probes
+            );
+        }
+    }
+}
+
+/// Stats since the previous call, which starts the next window.
+pub fn take_window() -> WindowStats {
+    COVERAGE.lock(|c| {
+        let mut coverage = c.borrow_mut();
+        coverage.update(Instant::now());
+        let stats = WindowStats {
+            sniffed: coverage.accumulated,
+            frames_seen: coverage.frames_seen,
+            fingerprints_dropped: coverage.fingerprints_dropped,
+            rssi_histogram: coverage.rssi_histogram,
+            rssi_gated: coverage.rssi_gated,
+        };
+        coverage.accumulated = Duration::from_ticks(0);
+        coverage.frames_seen = 0;
+        coverage.fingerprints_dropped = 0;
+        coverage.rssi_histogram = [0; RSSI_BINS];
+        coverage.rssi_gated = 0;
+        stats
+    })
+}
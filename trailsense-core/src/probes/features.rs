@@ -0,0 +1,224 @@
+use heapless::Vec as HeaplessVec;
+
+use crate::probes::fingerprint::Fingerprint;
+
+const ELEMENT_SSID: u8 = 0;
+const ELEMENT_SUPPORTED_RATES: u8 = 1;
+const ELEMENT_HT_CAPABILITIES: u8 = 45;
+const ELEMENT_EXTENDED_RATES: u8 = 50;
+const ELEMENT_EXTENDED_CAPABILITIES: u8 = 127;
+const ELEMENT_VHT_CAPABILITIES: u8 = 191;
+const ELEMENT_VENDOR_SPECIFIC: u8 = 221;
+const ELEMENT_EXTENSION: u8 = 255;
+/// Element ID extension of HE capabilities, carried in an extension element.
+const EXTENSION_HE_CAPABILITIES: u8 = 35;
+
+const FNV_OFFSET: u32 = 0x811c_9dc5;
+const FNV_PRIME: u32 = 0x0100_0193;
+
+/// # Probe Features
+///
+/// The information elements of a probe request body, independent of where they sit in the frame.
+/// Values longer than the capacities below are cut off, which only happens for malformed frames.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ProbeFeatures {
+    pub ssid_len: u8,
+    /// Empty SSID element, sent while scanning rather than looking for a known network.
+    pub wildcard_ssid: bool,
+    pub supported_rates: HeaplessVec<u8, 8>,
+    pub extended_rates: HeaplessVec<u8, 16>,
+    pub ht_capabilities: Option<HeaplessVec<u8, 26>>,
+    pub vht_capabilities: Option<HeaplessVec<u8, 12>>,
+    pub he_capabilities: Option<HeaplessVec<u8, 32>>,
+    pub extended_capabilities: Option<HeaplessVec<u8, 16>>,
+    pub vendor_ouis: HeaplessVec<[u8; 3], 8>,
+    /// Element IDs in the order they were sent.
+    pub element_order: HeaplessVec<u8, 32>,
+}
+
+impl ProbeFeatures {
+    /// Walks the elements of a probe request body, stopping at the first truncated one.
+    pub fn parse(body: &[u8]) -> Self {
+        let mut features = ProbeFeatures::default();
+        let mut rest = body;
+        while let [id, len, tail @ ..] = rest {
+            let Some(data) = tail.get(..usize::from(*len)) else {
+                break;
+            };
+            rest = &tail[data.len()..];
+            let _ = features.element_order.push(*id);
+
+            match *id {
+                ELEMENT_SSID => {
+                    features.ssid_len = *len;
+                    features.wildcard_ssid = data.is_empty();
+                }
+                ELEMENT_SUPPORTED_RATES => features.supported_rates = truncated(data),
+                ELEMENT_HT_CAPABILITIES => features.ht_capabilities = Some(truncated(data)),
+                ELEMENT_EXTENDED_RATES => features.extended_rates = truncated(data),
+                ELEMENT_EXTENDED_CAPABILITIES => {
+                    features.extended_capabilities = Some(truncated(data))
+                }
+                ELEMENT_VHT_CAPABILITIES => features.vht_capabilities = Some(truncated(data)),
+                ELEMENT_VENDOR_SPECIFIC => {
+                    if let [a, b, c, ..] = data {
+                        let _ = features.vendor_ouis.push([*a, *b, *c]);
+                    }
+                }
+                ELEMENT_EXTENSION => {
+                    if let [EXTENSION_HE_CAPABILITIES, caps @ ..] = data {
+                        features.he_capabilities = Some(truncated(caps));
+                    }
+                }
+                _ => {}
+            }
+        }
+        features
+    }
+
+    /// # Feature Fingerprint
+    ///
+    /// Each feature group is hashed into its own segment of the fingerprint, so a difference in one
+    /// group only flips bits of that segment. Within a segment the bits are a hash: a differing group
+    /// flips anywhere from none of them (a collision) to all, so the Hamming distance only bounds how
+    /// many groups differ and says nothing about how much. Above 0 a threshold merges by hash closeness.
+    /// The SSID length is left out, one device probing for several networks would split otherwise.
+    pub fn fingerprint(&self) -> Fingerprint {
+        let segments = [
+            (
+                3,
+                hash(&[
+                    self.supported_rates.as_slice(),
+                    self.extended_rates.as_slice(),
+                ]),
+            ),
+            (3, hash_optional(self.ht_capabilities.as_deref())),
+            (2, hash_optional(self.vht_capabilities.as_deref())),
+            (2, hash_optional(self.he_capabilities.as_deref())),
+            (2, hash_optional(self.extended_capabilities.as_deref())),
+            (2, hash(&[self.vendor_ouis.as_flattened()])),
+            (
+                2,
+                hash(&[
+                    &[u8::from(self.wildcard_ssid)],
+                    self.element_order.as_slice(),
+                ]),
+            ),
+        ];
+
+        let mut fingerprint: Fingerprint = 0;
+        for (bits, hash) in segments {
+            let folded = (hash ^ (hash >> 16)) & ((1 << bits) - 1);
+            fingerprint = (fingerprint << bits) | folded as Fingerprint;
+        }
+        fingerprint
+    }
+}
+
+fn truncated<const N: usize>(data: &[u8]) -> HeaplessVec<u8, N> {
+    data.iter().take(N).copied().collect()
+}
+
+/// FNV-1a over the parts, each prefixed with its length so bytes cannot move between parts unnoticed.
+fn hash(parts: &[&[u8]]) -> u32 {
+    let mut hash = FNV_OFFSET;
+    for part in parts {
+        for &byte in [part.len() as u8].iter().chain(part.iter()) {
+            hash = (hash ^ u32::from(byte)).wrapping_mul(FNV_PRIME);
+        }
+    }
+    hash
+}
+
+/// Keeps an absent element apart from an empty one.
+fn hash_optional(data: Option<&[u8]>) -> u32 {
+    hash(&[&[u8::from(data.is_some())], data.unwrap_or_default()])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Bits of the element order segment, the lowest ones of the fingerprint.
+    const ORDER_SEGMENT: Fingerprint = 0b11;
+
+    const RATES: [u8; 6] = [1, 4, 0x82, 0x84, 0x0b, 0x16];
+    const HT: [u8; 4] = [45, 2, 0xaa, 0xbb];
+    const VENDOR: [u8; 6] = [221, 4, 0x00, 0x50, 0xf2, 0x08];
+    const HE: [u8; 5] = [255, 3, 35, 0x01, 0x02];
+
+    fn body(ssid: &[u8], elements: &[&[u8]]) -> Vec<u8> {
+        let mut body = vec![ELEMENT_SSID, ssid.len() as u8];
+        body.extend_from_slice(ssid);
+        for element in elements {
+            body.extend_from_slice(element);
+        }
+        body
+    }
+
+    #[test]
+    fn parses_the_known_elements() {
+        let features = ProbeFeatures::parse(&body(b"", &[&RATES, &HT, &VENDOR, &HE]));
+        assert!(features.wildcard_ssid);
+        assert_eq!(features.supported_rates.as_slice(), &RATES[2..]);
+        assert_eq!(features.ht_capabilities.as_deref(), Some(&HT[2..]));
+        assert_eq!(features.vendor_ouis.as_slice(), &[[0x00, 0x50, 0xf2]]);
+        assert_eq!(features.he_capabilities.as_deref(), Some(&[0x01, 0x02][..]));
+        assert_eq!(features.element_order.as_slice(), &[0, 1, 45, 221, 255]);
+    }
+
+    #[test]
+    fn reordered_elements_only_change_the_order_segment() {
+        let sent = ProbeFeatures::parse(&body(b"", &[&RATES, &HT, &VENDOR, &HE]));
+        let reordered = ProbeFeatures::parse(&body(b"", &[&HE, &VENDOR, &HT, &RATES]));
+        assert_ne!(sent.element_order, reordered.element_order);
+        assert_eq!(
+            ProbeFeatures {
+                element_order: sent.element_order.clone(),
+                ..reordered.clone()
+            },
+            sent
+        );
+        assert_eq!(
+            (sent.fingerprint() ^ reordered.fingerprint()) & !ORDER_SEGMENT,
+            0
+        );
+    }
+
+    #[test]
+    fn ssid_length_does_not_change_the_fingerprint() {
+        let home = ProbeFeatures::parse(&body(b"home", &[&RATES, &HT]));
+        let office = ProbeFeatures::parse(&body(b"office-guest", &[&RATES, &HT]));
+        assert_eq!((home.ssid_len, office.ssid_len), (4, 12));
+        assert_eq!(home.fingerprint(), office.fingerprint());
+
+        // A wildcard probe is a different kind of request, it is kept apart.
+        let wildcard = ProbeFeatures::parse(&body(b"", &[&RATES, &HT]));
+        assert!(wildcard.wildcard_ssid && !home.wildcard_ssid);
+    }
+
+    #[test]
+    fn parsing_stops_at_a_truncated_element() {
+        let full = body(b"", &[&RATES, &HT, &VENDOR]);
+        // Cut inside the vendor element, the ones before it survive.
+        let features = ProbeFeatures::parse(&full[..full.len() - 2]);
+        assert_eq!(features.element_order.as_slice(), &[0, 1, 45]);
+        assert_eq!(features.ht_capabilities.as_deref(), Some(&HT[2..]));
+        assert!(features.vendor_ouis.is_empty());
+
+        // A lone ID without its length byte is dropped as well.
+        let features = ProbeFeatures::parse(&full[..2 + RATES.len() + 1]);
+        assert_eq!(features.element_order.as_slice(), &[0, 1]);
+        assert_eq!(ProbeFeatures::parse(&[]), ProbeFeatures::default());
+    }
+
+    #[test]
+    fn oversized_values_are_cut_to_capacity() {
+        let rates = [1, 10, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10];
+        let features = ProbeFeatures::parse(&body(b"", &[&rates]));
+        assert_eq!(
+            features.supported_rates.as_slice(),
+            &[1, 2, 3, 4, 5, 6, 7, 8]
+        );
+    }
+}
@@ -0,0 +1,171 @@
+extern crate alloc;
+use alloc::{string::String, vec::Vec};
+use core::cell::RefCell;
+
+use embassy_sync::blocking_mutex::{Mutex, raw::CriticalSectionRawMutex};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum FilterAction {
+    Allow,
+    Deny,
+}
+
+/// A rule on the transmitter address of a probe. `pattern` is an OUI (`54:8a:ba`) or a full
+/// MAC (`54:8a:ba:00:11:22`), hex with `:` or `-` separators.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct FilterRule {
+    pub action: FilterAction,
+    pub pattern: String,
+}
+
+impl FilterRule {
+    pub fn deny(pattern: &str) -> Self {
+        FilterRule {
+            action: FilterAction::Deny,
+            pattern: String::from(pattern),
+        }
+    }
+}
+
+/// Parses `pattern` into the address bytes it matches, 3 for an OUI and 6 for a full MAC.
+pub fn parse_pattern(pattern: &str) -> Option<([u8; 6], usize)> {
+    let mut bytes = [0u8; 6];
+    let mut len = 0;
+    for part in pattern.split([':', '-']) {
+        if len == bytes.len() || part.len() != 2 {
+            return None;
+        }
+        bytes[len] = u8::from_str_radix(part, 16).ok()?;
+        len += 1;
+    }
+    matches!(len, 3 | 6).then_some((bytes, len))
+}
+
+struct CompiledRule {
+    action: FilterAction,
+    pattern: String,
+    bytes: [u8; 6],
+    len: usize,
+    hits: u32,
+}
+
+impl CompiledRule {
+    fn matches(&self, mac: &[u8; 6]) -> bool {
+        mac[..self.len] == self.bytes[..self.len]
+    }
+}
+
+/// # MAC Filter
+///
+/// Rules are grouped by specificity: full MAC allow, full MAC deny, OUI deny, then OUI allow.
+/// So one device can be let through from a denied vendor, and once any OUI is allowed only
+/// those vendors pass.
+struct MacFilter {
+    rules: Vec<CompiledRule>,
+    not_allowed: u32,
+}
+
+impl MacFilter {
+    const fn new() -> Self {
+        MacFilter {
+            rules: Vec::new(),
+            not_allowed: 0,
+        }
+    }
+
+    fn allows(&mut self, mac: &[u8; 6]) -> bool {
+        let order = [
+            (6, FilterAction::Allow),
+            (6, FilterAction::Deny),
+            (3, FilterAction::Deny),
+            (3, FilterAction::Allow),
+        ];
+        for (len, action) in order {
+            let hit = self
+                .rules
+                .iter_mut()
+                .find(|r| r.len == len && r.action == action && r.matches(mac));
+            if let Some(rule) = hit {
+                rule.hits = rule.hits.saturating_add(1);
+                return action == FilterAction::Allow;
+            }
+        }
+
+        let allowlist = self
+            .rules
+            .iter()
+            .any(|r| r.len == 3 && r.action == FilterAction::Allow);
+        if allowlist {
+            self.not_allowed = self.not_allowed.saturating_add(1);
+            return false;
+        }
+        true
+    }
+}
+
+static FILTER: Mutex<CriticalSectionRawMutex, RefCell<MacFilter>> =
+    Mutex::new(RefCell::new(MacFilter::new()));
+
+/// Replaces the active rules, which also resets the counters. Invalid patterns are skipped,
+/// `Config::validate` rejects them before they get here.
+pub fn configure(rules: &[FilterRule]) {
+    let rules = rules
+        .iter()
+        .filter_map(|rule| {
+            let (bytes, len) = parse_pattern(&rule.pattern)?;
+            Some(CompiledRule {
+                action: rule.action,
+                pattern: rule.pattern.clone(),
+                bytes,
+                len,
+                hits: 0,
+            })
+        })
+        .collect();
+    FILTER.lock(|f| {
+        *f.borrow_mut() = MacFilter {
+            rules,
+            not_allowed: 0,
+        }
+    });
+}
+
+/// Whether a probe from `mac` should be counted, bumps the counter of the deciding rule.
+pub fn allows(mac: &[u8; 6]) -> bool {
+    FILTER.lock(|f| f.borrow_mut().allows(mac))
+}
+
+#[derive(Serialize, Debug)]
+pub struct RuleHits {
+    pub action: FilterAction,
+    pub pattern: String,
+    pub hits: u32,
+}
+
+#[derive(Serialize, Debug)]
+pub struct FilterStats {
+    /// In config order, so entries line up with `Config::mac_filter`.
+    pub rules: Vec<RuleHits>,
+    /// Dropped because an OUI allowlist exists and nothing on it matched.
+    pub not_allowed: u32,
+}
+
+pub fn stats() -> FilterStats {
+    FILTER.lock(|f| {
+        let filter = f.borrow();
+        FilterStats {
+            rules: filter
+                .rules
+                .iter()
+                .map(|r| RuleHits {
+                    action: r.action,
+                    pattern: r.pattern.clone(),
+                    hits: r.hits,
+                })
+                .collect(),
+            not_allowed: filter.not_allowed,
+        }
+    })
+}
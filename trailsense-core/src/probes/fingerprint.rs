@@ -0,0 +1,65 @@
+use crate::probes::models::MODEL_SIZE;
+
+/// # Fingerprint
+///
+/// One bit per classifier of the model, in the narrowest unsigned integer that holds `MODEL_SIZE` bits.
+/// Regenerating `models.rs` with more classifiers widens it everywhere.
+pub type Fingerprint = <Width<{ storage_bits(MODEL_SIZE) }> as FingerprintWidth>::Type;
+
+const _: () = assert!(
+    MODEL_SIZE <= 128,
+    "the model has more classifiers than the widest fingerprint"
+);
+
+pub struct Width<const BITS: usize>;
+
+pub trait FingerprintWidth {
+    type Type;
+}
+
+impl FingerprintWidth for Width<16> {
+    type Type = u16;
+}
+
+impl FingerprintWidth for Width<32> {
+    type Type = u32;
+}
+
+impl FingerprintWidth for Width<64> {
+    type Type = u64;
+}
+
+impl FingerprintWidth for Width<128> {
+    type Type = u128;
+}
+
+const fn storage_bits(model_size: usize) -> usize {
+    match model_size {
+        0..=16 => 16,
+        17..=32 => 32,
+        33..=64 => 64,
+        _ => 128,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fingerprint_is_the_narrowest_width_holding_the_model() {
+        assert_eq!(Fingerprint::BITS as usize, storage_bits(MODEL_SIZE));
+        // Every classifier's bit fits, the top one included.
+        let top: Fingerprint = 1 << (MODEL_SIZE - 1);
+        assert_eq!(
+            top.leading_zeros() as usize,
+            Fingerprint::BITS as usize - MODEL_SIZE
+        );
+    }
+
+    #[test]
+    fn storage_bits_rounds_up_to_an_integer_width() {
+        let widths = [1, 16, 17, 32, 33, 64, 65, 128].map(storage_bits);
+        assert_eq!(widths, [16, 16, 32, 32, 64, 64, 128, 128]);
+    }
+}
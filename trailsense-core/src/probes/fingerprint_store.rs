@@ -4,12 +4,16 @@ use core::cell::RefCell;
 use embassy_sync::blocking_mutex::{Mutex, raw::CriticalSectionRawMutex};
 use heapless::Vec as HeaplessVec;
 
+use crate::probes::fingerprint::Fingerprint;
+
 const MAX_FINGERPRINTS: usize = 2048;
 
-static FINGERPRINTS: Mutex<CriticalSectionRawMutex, RefCell<HeaplessVec<u16, MAX_FINGERPRINTS>>> =
-    Mutex::new(RefCell::new(HeaplessVec::new()));
+static FINGERPRINTS: Mutex<
+    CriticalSectionRawMutex,
+    RefCell<HeaplessVec<Fingerprint, MAX_FINGERPRINTS>>,
+> = Mutex::new(RefCell::new(HeaplessVec::new()));
 
-pub fn push(fingerprint: u16) -> bool {
+pub fn push(fingerprint: Fingerprint) -> bool {
     FINGERPRINTS.lock(|v| v.borrow_mut().push(fingerprint).is_ok())
 }
 
@@ -17,6 +21,6 @@ pub fn drain() {
     FINGERPRINTS.lock(|v| v.borrow_mut().clear());
 }
 
-pub fn snapshot() -> Vec<u16> {
+pub fn snapshot() -> Vec<Fingerprint> {
     FINGERPRINTS.lock(|v| v.borrow().iter().copied().collect())
 }
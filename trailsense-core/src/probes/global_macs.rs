@@ -0,0 +1,68 @@
+use core::cell::RefCell;
+
+use embassy_sync::blocking_mutex::{Mutex, raw::CriticalSectionRawMutex};
+use heapless::Vec as HeaplessVec;
+use sha2::{Digest, Sha256};
+
+use crate::rng;
+
+const MAX_GLOBAL_MACS: usize = 1024;
+
+/// # Global MAC Set
+///
+/// Devices with a globally unique address are counted exactly instead of by fingerprint.
+/// Only salted hashes are kept, sorted for lookup, and the salt is replaced every window so
+/// entries cannot be linked across windows or back to an address.
+struct MacSet {
+    salt: Option<[u8; 16]>,
+    hashes: HeaplessVec<u32, MAX_GLOBAL_MACS>,
+}
+
+impl MacSet {
+    fn hash(&mut self, mac: &[u8; 6]) -> u32 {
+        let salt = self.salt.get_or_insert_with(|| {
+            let mut salt = [0u8; 16];
+            rng::fill(&mut salt);
+            salt
+        });
+        let digest = Sha256::new()
+            .chain_update(salt)
+            .chain_update(mac)
+            .finalize();
+        u32::from_le_bytes([digest[0], digest[1], digest[2], digest[3]])
+    }
+}
+
+static GLOBAL_MACS: Mutex<CriticalSectionRawMutex, RefCell<MacSet>> =
+    Mutex::new(RefCell::new(MacSet {
+        salt: None,
+        hashes: HeaplessVec::new(),
+    }));
+
+/// The locally administered bit, set by devices that randomize their address.
+pub fn is_randomized(mac: &[u8; 6]) -> bool {
+    mac[0] & 0x02 != 0
+}
+
+/// Adds `mac` to this window's set, false if it was new and the set is full.
+pub fn insert(mac: &[u8; 6]) -> bool {
+    GLOBAL_MACS.lock(|s| {
+        let mut set = s.borrow_mut();
+        let hash = set.hash(mac);
+        match set.hashes.binary_search(&hash) {
+            Ok(_) => true,
+            Err(index) => set.hashes.insert(index, hash).is_ok(),
+        }
+    })
+}
+
+/// Distinct addresses seen since the previous call, which starts the next window with a new salt.
+pub fn take_count() -> u32 {
+    GLOBAL_MACS.lock(|s| {
+        let mut set = s.borrow_mut();
+        let count = set.hashes.len() as u32;
+        set.hashes.clear();
+        set.salt = None;
+        count
+    })
+}
@@ -0,0 +1,13 @@
+pub mod counter;
+pub mod coverage;
+pub mod features;
+pub mod filter;
+pub mod fingerprint;
+pub mod fingerprint_store;
+pub mod global_macs;
+// Generated by csv_to_rust.py, the alphas keep the digits of the training output.
+#[allow(clippy::excessive_precision)]
+pub mod models;
+pub mod pipeline;
+pub mod probe_parser;
+pub mod rssi;
@@ -0,0 +1,114 @@
+use core::cell::Cell;
+
+use embassy_sync::{
+    blocking_mutex::{Mutex, raw::CriticalSectionRawMutex},
+    channel::Channel,
+};
+use heapless::Vec as HeaplessVec;
+
+use crate::probes::{coverage, rssi::RxMeta};
+
+/// Longest MAC header, the part of the body the fingerprints look at and the FCS, longer probes are truncated.
+pub const MAX_PROBE_FRAME_LEN: usize = 28 + 256 + 4;
+const QUEUE_LEN: usize = 32;
+const MAC_HEADER_LEN: usize = 24;
+/// First frame control byte of a probe request: version 0, management type, subtype 4.
+const PROBE_REQUEST_FC: u8 = 0x40;
+
+pub struct CapturedProbe {
+    pub rx: RxMeta,
+    pub frame: HeaplessVec<u8, MAX_PROBE_FRAME_LEN>,
+}
+
+static PROBE_QUEUE: Channel<CriticalSectionRawMutex, CapturedProbe, QUEUE_LEN> = Channel::new();
+
+static QUEUE_DROPS: Mutex<CriticalSectionRawMutex, Cell<u32>> = Mutex::new(Cell::new(0));
+static RX_ERRORS: Mutex<CriticalSectionRawMutex, Cell<u32>> = Mutex::new(Cell::new(0));
+
+/// Counts a captured frame and queues it when it is a probe request. Frames the radio received with errors
+/// are dropped before anything else, a full queue drops the probe.
+pub fn capture(rx_error: bool, rx: RxMeta, data: &[u8]) {
+    if rx_error {
+        RX_ERRORS.lock(|e| e.set(e.get().saturating_add(1)));
+        return;
+    }
+    coverage::record_frame(rx.channel);
+
+    if data.len() < MAC_HEADER_LEN || data[0] != PROBE_REQUEST_FC {
+        return;
+    }
+    coverage::record_probe_request(rx.channel);
+
+    let len = data.len().min(MAX_PROBE_FRAME_LEN);
+    let mut frame = HeaplessVec::new();
+    // Cannot fail, `len` is within the capacity.
+    let _ = frame.extend_from_slice(&data[..len]);
+
+    if PROBE_QUEUE.try_send(CapturedProbe { rx, frame }).is_err() {
+        QUEUE_DROPS.lock(|d| d.set(d.get().saturating_add(1)));
+    }
+}
+
+/// Probes lost to a full queue since boot.
+pub fn queue_drops() -> u32 {
+    QUEUE_DROPS.lock(|d| d.get())
+}
+
+/// Frames dropped for a receive error since boot.
+pub fn rx_errors() -> u32 {
+    RX_ERRORS.lock(|e| e.get())
+}
+
+/// Waits for the next queued probe.
+pub async fn receive() -> CapturedProbe {
+    PROBE_QUEUE.receive().await
+}
+
+/// Whether every queued probe was taken.
+pub fn is_drained() -> bool {
+    PROBE_QUEUE.is_empty()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RX: RxMeta = RxMeta {
+        rssi: -60,
+        channel: 6,
+        rate: 0,
+        noise_floor: -95,
+    };
+
+    fn probe_request(len: usize) -> Vec<u8> {
+        let mut frame = vec![0u8; len];
+        frame[0] = PROBE_REQUEST_FC;
+        frame
+    }
+
+    fn queued() -> Vec<usize> {
+        core::iter::from_fn(|| PROBE_QUEUE.try_receive().ok())
+            .map(|probe| probe.frame.len())
+            .collect()
+    }
+
+    // One test, the queue and counters are shared statics.
+    #[test]
+    fn capture_queues_probe_requests_only() {
+        let errors = rx_errors();
+        capture(true, RX, &probe_request(40));
+        assert_eq!(rx_errors(), errors + 1);
+        assert!(queued().is_empty());
+
+        let mut beacon = probe_request(40);
+        beacon[0] = 0x80;
+        capture(false, RX, &beacon);
+        capture(false, RX, &probe_request(MAC_HEADER_LEN - 1));
+        assert!(queued().is_empty());
+
+        capture(false, RX, &probe_request(40));
+        capture(false, RX, &probe_request(MAX_PROBE_FRAME_LEN + 100));
+        assert_eq!(queued(), [40, MAX_PROBE_FRAME_LEN]);
+        assert_eq!(rx_errors(), errors + 1);
+    }
+}
@@ -0,0 +1,259 @@
+extern crate alloc;
+use core::cell::Cell;
+
+use embassy_sync::blocking_mutex::{Mutex, raw::CriticalSectionRawMutex};
+use ieee80211::{
+    GenericFrame,
+    common::{FrameType, ManagementFrameSubtype},
+    mgmt_frame::ManagementFrameHeader,
+    scroll::Pread,
+};
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::probes::{
+    coverage,
+    features::ProbeFeatures,
+    filter,
+    fingerprint::Fingerprint,
+    fingerprint_store, global_macs,
+    models::MODEL,
+    rssi::{self, RxMeta},
+};
+
+/// The frame control field, the management header follows it.
+const FRAME_CONTROL_LEN: usize = 2;
+const FCS_LEN: usize = 4;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum FingerprintMethod {
+    /// Parsed information elements, see `ProbeFeatures::fingerprint`. Not yet validated against ground truth,
+    /// opt in to compare its counts with the mask model.
+    Features,
+    /// The trained mask model over raw body bytes, the default.
+    Masks,
+}
+
+static METHOD: Mutex<CriticalSectionRawMutex, Cell<FingerprintMethod>> =
+    Mutex::new(Cell::new(FingerprintMethod::Masks));
+
+pub fn configure(method: FingerprintMethod) {
+    METHOD.lock(|m| m.set(method));
+}
+
+/// # Mask Fingerprint
+///
+/// Generate a fingerprint for the given probe data using the defined filters generated with a python script.
+/// Each filter outputs a single bit, which are concatenated to form the final fingerprint.
+///
+/// # Arguments
+///
+/// * `data` - A byte slice representing the probe data to be fingerprinted.
+///
+/// # Returns
+///
+/// A `Fingerprint` is returned, where each bit represents one bit of the filter.
+fn mask_fingerprint(data: &[u8]) -> Fingerprint {
+    let mut fingerprint: Fingerprint = 0;
+
+    for (idx, model) in MODEL.iter().enumerate() {
+        let masks = model.positive_mask.iter().zip(model.negative_mask);
+        let mut score: i32 = 0;
+        for (i, (byte, (positive, negative))) in data.iter().zip(masks).enumerate() {
+            let positive_bits = byte & positive;
+            let negative_bits = byte & negative;
+
+            // Debug assertion to catch any mask generation errors during development.
+            // The masks are designed to be disjoint, so this should never trigger.
+            debug_assert_eq!(
+                positive & negative,
+                0,
+                "Mask overlap detected at filter {} position {}: positive_mask={:#x}, negative_mask={:#x}",
+                idx,
+                i,
+                positive,
+                negative
+            );
+
+            score += positive_bits.count_ones() as i32;
+            score -= negative_bits.count_ones() as i32;
+        }
+
+        let bit = score >= model.threshold as i32;
+        fingerprint = (fingerprint << 1) | Fingerprint::from(bit);
+    }
+    fingerprint
+}
+
+/// Fingerprints a probe request body with the configured method and stores the result.
+fn fingerprint_probe(body: &[u8]) -> Fingerprint {
+    let fingerprint = match METHOD.lock(|m| m.get()) {
+        FingerprintMethod::Features => ProbeFeatures::parse(body).fingerprint(),
+        FingerprintMethod::Masks => mask_fingerprint(body),
+    };
+    if !fingerprint_store::push(fingerprint) {
+        warn!("Fingerprint overflow!");
+        coverage::record_fingerprint_dropped();
+    }
+    fingerprint
+}
+
+/// A probe request found in a captured frame.
+pub struct ProbeRequest<'a> {
+    pub source: [u8; 6],
+    pub body: &'a [u8],
+}
+
+/// # Parse Probe Request
+///
+/// Finds the transmitter and body of a probe request. The management header carries HT Control
+/// when the +HTC/Order flag is set. The sniffer appends the FCS, which is stripped when the last 4 bytes
+/// match the CRC of the rest; a frame truncated at capture has lost it already. A probe request carries at
+/// least its SSID element, so a frame that ends at its header is rejected rather than parsed as an empty body.
+pub fn parse_probe_request(data: &[u8]) -> Option<ProbeRequest<'_>> {
+    let data = strip_fcs(data);
+    let frame = GenericFrame::new(data, false).ok()?;
+    let fc = frame.frame_control_field();
+    if fc.frame_type() != FrameType::Management(ManagementFrameSubtype::ProbeRequest) {
+        return None;
+    }
+
+    let header: ManagementFrameHeader = data.pread_with(FRAME_CONTROL_LEN, fc.flags()).ok()?;
+    Some(ProbeRequest {
+        source: header.transmitter_address.into(),
+        body: frame_body(data, header.length_in_bytes())?,
+    })
+}
+
+fn frame_body(data: &[u8], header_len: usize) -> Option<&[u8]> {
+    data.get(header_len..).filter(|body| !body.is_empty())
+}
+
+fn strip_fcs(data: &[u8]) -> &[u8] {
+    let Some(split) = data.len().checked_sub(FCS_LEN) else {
+        return data;
+    };
+    let (frame, fcs) = data.split_at(split);
+    if crc32fast::hash(frame).to_le_bytes() == fcs {
+        frame
+    } else {
+        data
+    }
+}
+
+/// Parses one captured frame and records a probe request from a qualifying source inside the RSSI band.
+/// Globally unique addresses are counted by address, randomized ones by fingerprint.
+///
+/// The MAC filter runs before the RSSI gate, so filtered infrastructure stays out of the RSSI histogram
+/// a site is calibrated from, while filter hits also count sources outside the band.
+pub fn process_frame(data: &[u8], rx: &RxMeta) {
+    let Some(probe) = parse_probe_request(data) else {
+        return;
+    };
+    if !filter::allows(&probe.source) || !rssi::admit(rx.rssi) {
+        return;
+    }
+
+    if global_macs::is_randomized(&probe.source) {
+        fingerprint_probe(probe.body);
+    } else if !global_macs::insert(&probe.source) {
+        // Still counted, just approximately.
+        warn!("Global MAC set full, fingerprinting instead");
+        fingerprint_probe(probe.body);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::probes::pipeline::MAX_PROBE_FRAME_LEN;
+
+    const SOURCE: [u8; 6] = [0x02, 0x11, 0x22, 0x33, 0x44, 0x55];
+    const BODY: [u8; 6] = [0, 0, 1, 2, 0x82, 0x84];
+    const HT_CONTROL: [u8; 4] = [0xaa; 4];
+
+    fn header_len(htc: bool) -> usize {
+        ManagementFrameHeader {
+            ht_control: htc.then_some(HT_CONTROL),
+            ..Default::default()
+        }
+        .length_in_bytes()
+    }
+
+    /// Probe request with `body`, the +HTC/Order flag adds an HT Control field after the header.
+    fn probe_request(htc: bool, body: &[u8]) -> Vec<u8> {
+        let mut frame = vec![0x40, if htc { 0x80 } else { 0x00 }, 0, 0];
+        frame.extend_from_slice(&[0xff; 6]);
+        frame.extend_from_slice(&SOURCE);
+        frame.extend_from_slice(&[0xff; 6]);
+        frame.extend_from_slice(&[0x10, 0x00]);
+        if htc {
+            frame.extend_from_slice(&HT_CONTROL);
+        }
+        frame.extend_from_slice(body);
+        frame
+    }
+
+    fn with_fcs(mut frame: Vec<u8>) -> Vec<u8> {
+        let fcs = crc32fast::hash(&frame).to_le_bytes();
+        frame.extend_from_slice(&fcs);
+        frame
+    }
+
+    #[test]
+    fn plain_header_without_fcs() {
+        let frame = probe_request(false, &BODY);
+        let probe = parse_probe_request(&frame).unwrap();
+        assert_eq!(probe.source, SOURCE);
+        assert_eq!(probe.body, BODY);
+    }
+
+    #[test]
+    fn htc_header_is_skipped() {
+        let frame = probe_request(true, &BODY);
+        assert_eq!(frame.len(), header_len(true) + BODY.len());
+        let probe = parse_probe_request(&frame).unwrap();
+        assert_eq!(probe.source, SOURCE);
+        assert_eq!(probe.body, BODY);
+    }
+
+    #[test]
+    fn matching_fcs_is_stripped() {
+        for htc in [false, true] {
+            let frame = with_fcs(probe_request(htc, &BODY));
+            assert_eq!(parse_probe_request(&frame).unwrap().body, BODY);
+        }
+    }
+
+    #[test]
+    fn frame_cut_at_capture_keeps_everything_it_has() {
+        let body: Vec<u8> = (0..=255).chain(0..=255).collect();
+        let frame = with_fcs(probe_request(false, &body));
+        let captured = &frame[..MAX_PROBE_FRAME_LEN];
+        // The FCS was cut off with the end of the body, the last 4 bytes are body and stay.
+        let probe = parse_probe_request(captured).unwrap();
+        assert_eq!(probe.body, &body[..MAX_PROBE_FRAME_LEN - header_len(false)]);
+    }
+
+    #[test]
+    fn frames_ending_at_the_header_are_rejected() {
+        assert!(parse_probe_request(&probe_request(false, &[])).is_none());
+        assert!(parse_probe_request(&with_fcs(probe_request(false, &[]))).is_none());
+        assert!(parse_probe_request(&probe_request(true, &[])).is_none());
+        // Flag set but the frame too short for the HT Control field.
+        let short = probe_request(false, &[0, 0]);
+        let mut htc = short.clone();
+        htc[1] |= 0x80;
+        assert!(parse_probe_request(&short).is_some());
+        assert!(parse_probe_request(&htc).is_none());
+    }
+
+    #[test]
+    fn other_frames_are_ignored() {
+        let mut beacon = probe_request(false, &BODY);
+        beacon[0] = 0x80;
+        assert!(parse_probe_request(&beacon).is_none());
+        assert!(parse_probe_request(&[0x40, 0x00]).is_none());
+    }
+}
@@ -0,0 +1,51 @@
+use core::cell::Cell;
+
+use embassy_sync::blocking_mutex::{Mutex, raw::CriticalSectionRawMutex};
+
+use crate::probes::coverage;
+
+/// 10 dB bins, the first holds everything up to -91 dBm and the last everything from -30 dBm on.
+pub const RSSI_BINS: usize = 8;
+const RSSI_FLOOR_DBM: i16 = -100;
+const RSSI_BIN_WIDTH_DB: i16 = 10;
+
+/// Receive metadata of a captured frame.
+#[derive(Clone, Copy, Debug)]
+pub struct RxMeta {
+    pub rssi: i8,
+    pub channel: u8,
+    /// PHY rate code as reported by the driver.
+    pub rate: u8,
+    pub noise_floor: i8,
+}
+
+#[derive(Clone, Copy)]
+struct RssiBand {
+    min: Option<i8>,
+    max: Option<i8>,
+}
+
+static BAND: Mutex<CriticalSectionRawMutex, Cell<RssiBand>> = Mutex::new(Cell::new(RssiBand {
+    min: None,
+    max: None,
+}));
+
+pub fn configure(min: Option<i8>, max: Option<i8>) {
+    BAND.lock(|b| b.set(RssiBand { min, max }));
+}
+
+pub fn bin(rssi: i8) -> usize {
+    let index = (i16::from(rssi) - RSSI_FLOOR_DBM) / RSSI_BIN_WIDTH_DB;
+    index.clamp(0, RSSI_BINS as i16 - 1) as usize
+}
+
+/// # RSSI Gate
+///
+/// Whether a probe received at `rssi` is inside the counting zone. Every probe lands in the window's
+/// histogram either way, which is what a site is calibrated from.
+pub fn admit(rssi: i8) -> bool {
+    let band = BAND.lock(|b| b.get());
+    let admitted = band.min.is_none_or(|min| rssi >= min) && band.max.is_none_or(|max| rssi <= max);
+    coverage::record_rssi(rssi, admitted);
+    admitted
+}
@@ -0,0 +1,19 @@
+use core::cell::Cell;
+
+use embassy_sync::blocking_mutex::{Mutex, raw::CriticalSectionRawMutex};
+
+/// Fills a buffer with random bytes.
+pub type Source = fn(&mut [u8]);
+
+/// Hardware RNG of the target, installed once at boot before any task runs.
+static SOURCE: Mutex<CriticalSectionRawMutex, Cell<Option<Source>>> = Mutex::new(Cell::new(None));
+
+pub fn install(source: Source) {
+    SOURCE.lock(|s| s.set(Some(source)));
+}
+
+/// Fills `bytes` from the installed RNG, used for salts and nonces.
+pub fn fill(bytes: &mut [u8]) {
+    let source = SOURCE.lock(Cell::get).expect("rng::install runs at boot");
+    source(bytes);
+}
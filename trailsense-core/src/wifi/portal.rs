@@ -0,0 +1,166 @@
+extern crate alloc;
+use alloc::{string::String, vec::Vec};
+use core::net::Ipv4Addr;
+
+/// Builds an A record answer for the first question of `query`, `None` for anything that is not a plain query.
+pub fn dns_answer(query: &[u8], ip: Ipv4Addr) -> Option<Vec<u8>> {
+    const HEADER_LEN: usize = 12;
+    if query.len() < HEADER_LEN || query[2] & 0x80 != 0 || query[4..6] != [0, 1] {
+        return None;
+    }
+
+    // Question: length prefixed labels ending in 0, then type and class.
+    let mut end = HEADER_LEN;
+    loop {
+        let label = *query.get(end)? as usize;
+        end += 1;
+        if label == 0 {
+            break;
+        }
+        if label & 0xC0 != 0 {
+            return None;
+        }
+        end += label;
+    }
+    end += 4;
+    let question = query.get(HEADER_LEN..end)?;
+
+    let mut answer = Vec::with_capacity(end + 16);
+    answer.extend_from_slice(&query[0..2]);
+    // Response, recursion desired and available, no error.
+    answer.extend_from_slice(&[0x81, 0x80]);
+    answer.extend_from_slice(&[0, 1, 0, 1, 0, 0, 0, 0]);
+    answer.extend_from_slice(question);
+    // Pointer to the name at offset 12, type A, class IN, TTL 60 s, 4 byte address.
+    answer.extend_from_slice(&[0xC0, 0x0C, 0, 1, 0, 1, 0, 0, 0, 60, 0, 4]);
+    answer.extend_from_slice(&ip.octets());
+    Some(answer)
+}
+
+/// Whether `request` holds the whole head and the body its `Content-Length` announces.
+pub fn request_complete(request: &[u8]) -> bool {
+    let Some(header_end) = find(request, b"\r\n\r\n") else {
+        return false;
+    };
+    let body_len = content_length(&request[..header_end]).unwrap_or(0);
+    request.len() >= header_end + 4 + body_len
+}
+
+fn content_length(headers: &[u8]) -> Option<usize> {
+    core::str::from_utf8(headers)
+        .ok()?
+        .lines()
+        .find_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                value.trim().parse().ok()
+            } else {
+                None
+            }
+        })
+}
+
+pub fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+/// Decodes an `application/x-www-form-urlencoded` body.
+pub fn parse_form(body: &[u8]) -> Vec<(String, String)> {
+    body.split(|&b| b == b'&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let mut parts = pair.splitn(2, |&b| b == b'=');
+            let key = url_decode(parts.next().unwrap_or(&[]));
+            let value = url_decode(parts.next().unwrap_or(&[]));
+            (key, value)
+        })
+        .collect()
+}
+
+fn url_decode(value: &[u8]) -> String {
+    let mut out = Vec::with_capacity(value.len());
+    let mut i = 0;
+    while i < value.len() {
+        match value[i] {
+            b'+' => out.push(b' '),
+            b'%' => {
+                let byte = value
+                    .get(i + 1..i + 3)
+                    .and_then(|hex| core::str::from_utf8(hex).ok())
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+                match byte {
+                    Some(b) => {
+                        out.push(b);
+                        i += 2;
+                    }
+                    None => out.push(b'%'),
+                }
+            }
+            b => out.push(b),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PORTAL: Ipv4Addr = Ipv4Addr::new(192, 168, 4, 1);
+
+    fn pair(key: &str, value: &str) -> (String, String) {
+        (key.to_string(), value.to_string())
+    }
+
+    #[test]
+    fn parse_form_decodes_pairs_in_order() {
+        assert_eq!(
+            parse_form(b"ssid0=My+Net%21&password0=a%26b%3Dc&api_url=https%3A%2F%2Fx.example"),
+            [
+                pair("ssid0", "My Net!"),
+                pair("password0", "a&b=c"),
+                pair("api_url", "https://x.example"),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_form_keeps_empty_and_missing_values() {
+        assert_eq!(
+            parse_form(b"ssid1=&&flag&=x"),
+            [pair("ssid1", ""), pair("flag", ""), pair("", "x")]
+        );
+        assert!(parse_form(b"").is_empty());
+    }
+
+    #[test]
+    fn url_decode_keeps_malformed_escapes_literally() {
+        assert_eq!(url_decode(b"%zz%4"), "%zz%4");
+        assert_eq!(url_decode(b"100%"), "100%");
+        assert_eq!(url_decode(b"%41%62+c"), "Ab c");
+    }
+
+    #[test]
+    fn url_decode_handles_utf8_and_replaces_invalid_bytes() {
+        assert_eq!(url_decode(b"caf%C3%A9"), "caf\u{e9}");
+        assert_eq!(url_decode(b"%FF"), "\u{fffd}");
+    }
+
+    #[test]
+    fn dns_answer_points_the_question_at_the_portal() {
+        let mut query = alloc::vec![0x12, 0x34, 0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0];
+        query.extend_from_slice(b"\x03www\x07example\x03com\x00");
+        query.extend_from_slice(&[0, 1, 0, 1]);
+
+        let answer = dns_answer(&query, PORTAL).unwrap();
+        assert_eq!(&answer[..2], &[0x12, 0x34]);
+        assert_eq!(&answer[12..query.len()], &query[12..]);
+        assert_eq!(&answer[answer.len() - 4..], &PORTAL.octets());
+
+        assert!(dns_answer(&query[..20], PORTAL).is_none());
+        // Responses are not answered.
+        query[2] |= 0x80;
+        assert!(dns_answer(&query, PORTAL).is_none());
+    }
+}
@@ -0,0 +1,204 @@
+extern crate alloc;
+use alloc::{string::String, vec::Vec};
+use core::cmp::Reverse;
+
+use crate::config::WifiNetwork;
+
+/// Weaker networks are only joined when no known network with a usable signal is in range.
+const MIN_USABLE_RSSI: i8 = -85;
+
+/// # Network Selector
+///
+/// Picks which known network to join from a scan and moves on to the next one after repeated failures.
+pub struct NetworkSelector {
+    /// Networks that failed too often, skipped until every known network did.
+    excluded: Vec<String>,
+    current: Option<String>,
+    failures: u8,
+}
+
+impl Default for NetworkSelector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NetworkSelector {
+    pub fn new() -> Self {
+        NetworkSelector {
+            excluded: Vec::new(),
+            current: None,
+            failures: 0,
+        }
+    }
+
+    pub fn current(&self) -> Option<&str> {
+        self.current.as_deref()
+    }
+
+    /// `visible` holds the SSID and RSSI of every scanned access point.
+    /// Known networks in range rank by usable signal, then priority, then RSSI. With none in range
+    /// the known networks are tried in priority order, which also covers hidden SSIDs.
+    pub fn select<'a>(
+        &mut self,
+        networks: &'a [WifiNetwork],
+        visible: &[(&str, i8)],
+    ) -> Option<&'a WifiNetwork> {
+        if networks.iter().all(|n| self.excluded.contains(&n.ssid)) {
+            self.excluded.clear();
+        }
+
+        let candidates = networks
+            .iter()
+            .enumerate()
+            .filter(|(_, n)| !self.excluded.contains(&n.ssid));
+
+        let in_range = candidates
+            .clone()
+            .filter_map(|(priority, network)| {
+                let rssi = visible
+                    .iter()
+                    .filter(|(ssid, _)| *ssid == network.ssid)
+                    .map(|(_, rssi)| *rssi)
+                    .max()?;
+                Some((priority, network, rssi))
+            })
+            .min_by_key(|(priority, _, rssi)| (*rssi < MIN_USABLE_RSSI, *priority, Reverse(*rssi)))
+            .map(|(_, network, _)| network);
+
+        let chosen = in_range.or_else(|| candidates.map(|(_, n)| n).next());
+        self.current = chosen.map(|n| n.ssid.clone());
+        self.failures = 0;
+        chosen
+    }
+
+    pub fn record_success(&mut self) {
+        self.failures = 0;
+        self.excluded.clear();
+    }
+
+    /// Returns true when the current network reached `failover_after` failures in a row and is now
+    /// skipped, the caller then selects again.
+    pub fn record_failure(&mut self, failover_after: u8) -> bool {
+        self.failures = self.failures.saturating_add(1);
+        if self.failures < failover_after {
+            return false;
+        }
+        if let Some(ssid) = self.current.take() {
+            self.excluded.push(ssid);
+        }
+        self.failures = 0;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+
+    fn networks(ssids: &[&str]) -> Vec<WifiNetwork> {
+        ssids
+            .iter()
+            .map(|ssid| WifiNetwork {
+                ssid: ssid.to_string(),
+                password: String::new(),
+            })
+            .collect()
+    }
+
+    fn selected<'a>(
+        selector: &mut NetworkSelector,
+        networks: &'a [WifiNetwork],
+        visible: &[(&str, i8)],
+    ) -> Option<&'a str> {
+        selector
+            .select(networks, visible)
+            .map(|network| network.ssid.as_str())
+    }
+
+    #[test]
+    fn priority_wins_among_usable_signals() {
+        let known = networks(&["visitor", "ranger"]);
+        let mut selector = NetworkSelector::new();
+        assert_eq!(
+            selected(&mut selector, &known, &[("ranger", -40), ("visitor", -70)]),
+            Some("visitor")
+        );
+        assert_eq!(selector.current(), Some("visitor"));
+    }
+
+    #[test]
+    fn usable_signal_beats_priority() {
+        let known = networks(&["visitor", "ranger"]);
+        let mut selector = NetworkSelector::new();
+        assert_eq!(
+            selected(&mut selector, &known, &[("ranger", -60), ("visitor", -90)]),
+            Some("ranger")
+        );
+    }
+
+    #[test]
+    fn weak_networks_rank_by_priority_then_rssi() {
+        let known = networks(&["visitor", "ranger"]);
+        let mut selector = NetworkSelector::new();
+        assert_eq!(
+            selected(&mut selector, &known, &[("ranger", -88), ("visitor", -95)]),
+            Some("visitor")
+        );
+        // Several access points of one network count with the strongest.
+        let known = networks(&["mesh"]);
+        assert_eq!(
+            selected(&mut selector, &known, &[("mesh", -95), ("mesh", -50)]),
+            Some("mesh")
+        );
+    }
+
+    #[test]
+    fn unknown_networks_are_ignored_and_none_in_range_falls_back_to_priority() {
+        let known = networks(&["visitor", "ranger"]);
+        let mut selector = NetworkSelector::new();
+        assert_eq!(
+            selected(&mut selector, &known, &[("other", -20)]),
+            Some("visitor")
+        );
+        assert_eq!(selected(&mut selector, &known, &[]), Some("visitor"));
+        assert_eq!(selected(&mut selector, &[], &[("other", -20)]), None);
+        assert_eq!(selector.current(), None);
+    }
+
+    #[test]
+    fn repeated_failures_move_on_to_the_next_network() {
+        let known = networks(&["visitor", "ranger"]);
+        let visible = [("ranger", -60), ("visitor", -50)];
+        let mut selector = NetworkSelector::new();
+        assert_eq!(selected(&mut selector, &known, &visible), Some("visitor"));
+
+        assert!(!selector.record_failure(3));
+        assert!(!selector.record_failure(3));
+        assert!(selector.record_failure(3));
+        assert_eq!(selected(&mut selector, &known, &visible), Some("ranger"));
+
+        // Once every network failed the exclusions are reset and priority applies again.
+        for _ in 0..2 {
+            assert!(!selector.record_failure(3));
+        }
+        assert!(selector.record_failure(3));
+        assert_eq!(selected(&mut selector, &known, &visible), Some("visitor"));
+    }
+
+    #[test]
+    fn success_clears_failures_and_exclusions() {
+        let known = networks(&["visitor", "ranger"]);
+        let mut selector = NetworkSelector::new();
+        selected(&mut selector, &known, &[]);
+        assert!(selector.record_failure(1));
+        assert_eq!(selected(&mut selector, &known, &[]), Some("ranger"));
+
+        selector.record_success();
+        assert!(!selector.record_failure(2));
+        selector.record_success();
+        assert!(!selector.record_failure(2));
+        assert_eq!(selected(&mut selector, &known, &[]), Some("visitor"));
+    }
+}
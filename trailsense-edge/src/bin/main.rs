@@ -13,18 +13,31 @@ use esp_backtrace as _;
 use esp_hal::clock::CpuClock;
 use esp_hal::peripherals::Peripherals;
 use esp_hal::rng::Rng;
+use esp_hal::rtc_cntl::Rtc;
+use esp_storage::FlashStorage;
 
 use embassy_time::{Duration, Timer};
 use esp_hal::timer::timg::TimerGroup;
+#[cfg(feature = "uplink-gsm")]
+use esp_hal::uart::{Config as UartConfig, Uart};
+use esp_radio::ble::controller::BleConnector;
 use log::{error, info};
 use static_cell::StaticCell;
+#[cfg(feature = "uplink-gsm")]
+use trailsense_edge::network::factory::build_gsm_transport;
+#[cfg(feature = "uplink-wifi")]
+use trailsense_edge::network::factory::build_wifi_transport;
 use trailsense_edge::{
+    ble, clock, config,
     network::{self, factory::build_active_transport},
-    probes::probe_parser::read_packet,
+    packages::package_store,
+    probes::pipeline::{self, capture_packet},
+    storage,
     wifi::{self, manager::WifiCmd, tasks::WifiControlCmd},
 };
 
 extern crate alloc;
+use alloc::vec::Vec;
 
 // This creates a default app-descriptor required by the esp-idf bootloader.
 // For more information see: <https://docs.espressif.com/projects/esp-idf/en/stable/esp32/api-reference/system/app_image_format.html#application-description>
@@ -35,6 +48,10 @@ static WIFI_COMMAND_CHANNEL: Channel<CriticalSectionRawMutex, WifiCmd, 4> = Chan
 static WIFI_CONTROL_CHANNEL: Channel<CriticalSectionRawMutex, WifiControlCmd, 4> = Channel::new();
 const INIT_RETRY_DELAY: Duration = Duration::from_secs(5);
 const FATAL_SLEEP: Duration = Duration::from_secs(1);
+const PACKAGES_PARTITION: &str = "packages";
+const CONFIG_PARTITION: &str = "config";
+#[cfg(feature = "uplink-gsm")]
+const GSM_BAUDRATE: u32 = 115_200;
 
 #[allow(
     clippy::large_stack_frames,
@@ -56,6 +73,20 @@ async fn main(spawner: Spawner) -> ! {
     let timg0 = TimerGroup::new(peripherals.TIMG0);
     esp_rtos::start(timg0.timer0);
 
+    // Salts and nonces draw from the hardware RNG, which has its full entropy once the radio runs.
+    trailsense_core::rng::install(|bytes| Rng::new().read(bytes));
+
+    let rtc = Rtc::new(peripherals.LPWR);
+    storage::init(FlashStorage::new(peripherals.FLASH));
+    config::init(storage::find_partition(CONFIG_PARTITION));
+    match storage::find_partition(PACKAGES_PARTITION) {
+        Some(partition) => package_store::init_persistence(partition, rtc.current_time_us()).await,
+        None => error!(
+            "Partition '{}' not found, packages are RAM only",
+            PACKAGES_PARTITION
+        ),
+    }
+
     let radio_init = loop {
         match esp_radio::init() {
             Ok(r) => break r,
@@ -69,7 +100,7 @@ async fn main(spawner: Spawner) -> ! {
         }
     };
 
-    let radio = RADIO_CELL.uninit().write(radio_init);
+    let radio: &'static esp_radio::Controller<'static> = RADIO_CELL.uninit().write(radio_init);
 
     let (wifi_controller, interfaces) =
         match esp_radio::wifi::new(radio, peripherals.WIFI, Default::default()) {
@@ -97,10 +128,60 @@ async fn main(spawner: Spawner) -> ! {
         error!("Failed to spawn net task: {}", e);
     }
 
+    // The portal tasks idle until the connect task switches to the provisioning access point.
+    let (ap_stack, ap_runner) = wifi::init_ap_stack(&mut rng, interfaces.ap);
+    if let Err(e) = spawner.spawn(wifi::tasks::net_task(ap_runner)) {
+        error!("Failed to spawn portal net task: {}", e);
+    }
+    if let Err(e) = spawner.spawn(wifi::portal::dhcp_task(ap_stack)) {
+        error!("Failed to spawn portal DHCP task: {}", e);
+    }
+    if let Err(e) = spawner.spawn(wifi::portal::dns_task(ap_stack)) {
+        error!("Failed to spawn portal DNS task: {}", e);
+    }
+    if let Err(e) = spawner.spawn(wifi::portal::http_task(ap_stack)) {
+        error!("Failed to spawn portal HTTP task: {}", e);
+    }
+
+    // BLE is a maintenance path only, the node keeps counting and uploading without it.
+    match BleConnector::new(radio, peripherals.BT, Default::default()) {
+        Ok(connector) => {
+            if let Err(e) = spawner.spawn(ble::ble_task(connector, WIFI_CONTROL_CHANNEL.sender())) {
+                error!("Failed to spawn BLE task: {}", e);
+            }
+        }
+        Err(e) => error!("Failed to initialize BLE controller: {:?}", e),
+    }
+
+    if let Err(e) = spawner.spawn(clock::sntp::sntp_task(ctx.stack)) {
+        error!("Failed to spawn SNTP task: {}", e);
+    }
+
     info!("Connection is up");
 
+    // Wi-Fi is the primary uplink, the modem only takes over when it is unreachable.
+    let mut links = Vec::new();
+
     #[cfg(feature = "uplink-wifi")]
-    let transport = build_active_transport(ctx, WIFI_CONTROL_CHANNEL.sender());
+    links.push(build_wifi_transport(ctx, WIFI_CONTROL_CHANNEL.sender()));
+
+    #[cfg(feature = "uplink-gsm")]
+    {
+        let uart_config = UartConfig::default().with_baudrate(GSM_BAUDRATE);
+        let gsm_uart = match Uart::new(peripherals.UART2, uart_config) {
+            Ok(uart) => uart
+                .with_rx(peripherals.GPIO16)
+                .with_tx(peripherals.GPIO17)
+                .into_async(),
+            Err(e) => {
+                error!("Failed to initialize GSM UART (fatal): {:?}", e);
+                fatal_idle().await;
+            }
+        };
+        links.push(build_gsm_transport(gsm_uart));
+    }
+
+    let transport = build_active_transport(links);
 
     if let Err(e) = spawner.spawn(network::uploader::uploader_task(
         transport,
@@ -109,9 +190,13 @@ async fn main(spawner: Spawner) -> ! {
         error!("Failed to spawn uploader task: {}", e);
     }
 
+    if let Err(e) = spawner.spawn(pipeline::probe_task()) {
+        error!("Failed to spawn probe task: {}", e);
+    }
+
     if let Err(e) = spawner.spawn(wifi::manager::wifi_manager_task(
         interfaces.sniffer,
-        read_packet,
+        capture_packet,
         WIFI_COMMAND_CHANNEL.receiver(),
     )) {
         error!("Failed to spawn wifi manager task: {}", e);
@@ -0,0 +1,328 @@
+extern crate alloc;
+use alloc::{format, string::String, vec::Vec};
+use core::cell::{Cell, RefCell};
+
+use bleps::{
+    ad_structure::{
+        AdStructure, BR_EDR_NOT_SUPPORTED, LE_GENERAL_DISCOVERABLE, create_advertising_data,
+    },
+    async_attribute_server::AttributeServer,
+    asynch::Ble,
+    attribute_server::NotificationData,
+    gatt,
+};
+use embassy_sync::{
+    blocking_mutex::{Mutex, raw::CriticalSectionRawMutex},
+    channel::Sender,
+};
+use embassy_time::{Duration, Instant, Timer};
+use esp_radio::ble::controller::BleConnector;
+use log::{error, info, warn};
+
+use trailsense_core::ble::{UnlockGuard, password_matches, read_at, write_at};
+
+use crate::{config, status, wifi::tasks::WifiControlCmd};
+
+const ADVERTISE_RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// Control characteristic commands.
+const CMD_RECONNECT: u8 = 0x01;
+const CMD_RESTART_CONTROLLER: u8 = 0x02;
+
+#[derive(Debug)]
+pub enum BleError {
+    Hci,
+    Advertising,
+}
+
+#[derive(Clone, Copy)]
+enum Field {
+    WifiSsid,
+    WifiPassword,
+    ApiUrl,
+}
+
+/// Values written over BLE, only applied together by a reconnect command.
+#[derive(Default)]
+struct Staged {
+    wifi_ssid: Option<Vec<u8>>,
+    wifi_password: Option<Vec<u8>>,
+    api_url: Option<Vec<u8>>,
+}
+
+impl Staged {
+    fn slot(&mut self, field: Field) -> &mut Option<Vec<u8>> {
+        match field {
+            Field::WifiSsid => &mut self.wifi_ssid,
+            Field::WifiPassword => &mut self.wifi_password,
+            Field::ApiUrl => &mut self.api_url,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.wifi_ssid.is_none() && self.wifi_password.is_none() && self.api_url.is_none()
+    }
+
+    /// A written SSID becomes the preferred network, a password alone updates the preferred network.
+    fn apply(self) -> Result<(), String> {
+        let text = |value: Vec<u8>| String::from_utf8(value).map_err(|_| String::from("not UTF-8"));
+        let mut config = config::Config::clone(&config::get());
+        let password = self.wifi_password.map(text).transpose()?;
+        let ssid = match self.wifi_ssid {
+            Some(ssid) => Some(text(ssid)?),
+            None if password.is_some() => config.wifi_networks.first().map(|n| n.ssid.clone()),
+            None => None,
+        };
+        match ssid {
+            Some(ssid) => {
+                config.promote_wifi_network(&ssid, password.as_deref().unwrap_or_default())
+            }
+            None if password.is_some() => return Err(String::from("no Wi-Fi network to update")),
+            None => {}
+        }
+        if let Some(api_url) = self.api_url {
+            config.api_url = text(api_url)?;
+        }
+        config::update(config).map_err(|e| format!("{:?}", e))
+    }
+}
+
+/// Kept across connections, so reconnecting does not reset the backoff.
+static UNLOCK_GUARD: Mutex<CriticalSectionRawMutex, Cell<UnlockGuard>> =
+    Mutex::new(Cell::new(UnlockGuard::NEW));
+
+/// State of one BLE connection. Writes are refused until the unlock characteristic received the
+/// portal password.
+struct Session {
+    unlocked: bool,
+    unlock_attempt: Vec<u8>,
+    staged: Staged,
+    last_result: String,
+}
+
+impl Session {
+    fn new() -> Self {
+        Session {
+            unlocked: false,
+            unlock_attempt: Vec::new(),
+            staged: Staged::default(),
+            last_result: String::from("ok"),
+        }
+    }
+
+    fn unlock(&mut self, offset: usize, data: &[u8]) {
+        let now = Instant::now();
+        let guard = UNLOCK_GUARD.lock(Cell::get);
+        if now < guard.retry_at {
+            warn!(
+                "BLE unlock refused, backing off after {} failures",
+                guard.failures
+            );
+            self.unlock_attempt.clear();
+            self.last_result = String::from("retry later");
+            return;
+        }
+
+        write_at(&mut self.unlock_attempt, offset, data);
+        let config = config::get();
+        let password = &config.portal_password;
+        self.unlocked = password_matches(&self.unlock_attempt, password);
+        if self.unlocked {
+            UNLOCK_GUARD.lock(|g| g.set(UnlockGuard::NEW));
+        } else if self.unlock_attempt.len() >= password.len() {
+            // Shorter attempts may still be the first chunks of a long write.
+            UNLOCK_GUARD.lock(|g| g.set(g.get().failed(now)));
+            warn!("BLE unlock failed");
+        }
+        self.last_result = String::from(if self.unlocked { "ok" } else { "locked" });
+    }
+
+    fn stage(&mut self, field: Field, offset: usize, data: &[u8]) {
+        if !self.unlocked {
+            warn!("BLE write refused, session locked");
+            self.last_result = String::from("locked");
+            return;
+        }
+        write_at(
+            self.staged.slot(field).get_or_insert_with(Vec::new),
+            offset,
+            data,
+        );
+    }
+
+    fn control(
+        &mut self,
+        data: &[u8],
+        control_sender: &Sender<'static, CriticalSectionRawMutex, WifiControlCmd, 4>,
+    ) {
+        if !self.unlocked {
+            warn!("BLE command refused, session locked");
+            self.last_result = String::from("locked");
+            return;
+        }
+
+        let cmd = match data.first() {
+            Some(&CMD_RECONNECT) => WifiControlCmd::Reconnect,
+            Some(&CMD_RESTART_CONTROLLER) => WifiControlCmd::RestartController,
+            _ => {
+                self.last_result = String::from("unknown command");
+                return;
+            }
+        };
+
+        if !self.staged.is_empty() {
+            if let Err(e) = core::mem::take(&mut self.staged).apply() {
+                error!("BLE config update rejected: {}", e);
+                self.last_result = e;
+                return;
+            }
+            info!("Config updated over BLE");
+        }
+
+        self.last_result = match control_sender.try_send(cmd) {
+            Ok(()) => String::from("ok"),
+            Err(_) => String::from("busy"),
+        };
+    }
+}
+
+fn now_ms() -> u64 {
+    Instant::now().as_millis()
+}
+
+/// # BLE Task
+///
+/// Maintenance GATT service that keeps working when the Wi-Fi uplink does not. Advertises as
+/// `Config::node_name` and serves one connection at a time.
+///
+/// - `...0001` write: Wi-Fi SSID, saved as the preferred network
+/// - `...0002` write: Wi-Fi password
+/// - `...0003` read/write: ingest API URL
+/// - `...0004` read: `status::NodeStatus` as JSON
+/// - `...0005` read/write: command byte (1 reconnect, 2 restart controller), reads back the last result
+/// - `...0006` write: portal password, unlocks the writes above, repeated failures back off
+///
+/// Written values are staged and saved by the next command, so credentials and URL change together.
+#[embassy_executor::task]
+pub async fn ble_task(
+    connector: BleConnector<'static>,
+    control_sender: Sender<'static, CriticalSectionRawMutex, WifiControlCmd, 4>,
+) {
+    // Unlocking needs the portal password, without one nothing could be provisioned anyway.
+    if config::get().portal_password.is_empty() {
+        error!("No portal password and no device secret, BLE provisioning disabled");
+        return;
+    }
+
+    let mut ble = Ble::new(connector, now_ms);
+
+    loop {
+        if let Err(e) = advertise(&mut ble).await {
+            error!("Failed to start BLE advertising: {:?}", e);
+            Timer::after(ADVERTISE_RETRY_DELAY).await;
+            continue;
+        }
+        info!("BLE advertising");
+
+        serve(&mut ble, &control_sender).await;
+        info!("BLE client disconnected");
+    }
+}
+
+async fn advertise(ble: &mut Ble<BleConnector<'static>>) -> Result<(), BleError> {
+    let name = config::get().node_name();
+    let data = create_advertising_data(&[
+        AdStructure::Flags(LE_GENERAL_DISCOVERABLE | BR_EDR_NOT_SUPPORTED),
+        AdStructure::CompleteLocalName(&name),
+    ])
+    .map_err(|_| BleError::Advertising)?;
+
+    ble.init().await.map_err(|_| BleError::Hci)?;
+    ble.cmd_set_le_advertising_parameters()
+        .await
+        .map_err(|_| BleError::Hci)?;
+    ble.cmd_set_le_advertising_data(data)
+        .await
+        .map_err(|_| BleError::Hci)?;
+    ble.cmd_set_le_advertise_enable(true)
+        .await
+        .map_err(|_| BleError::Hci)?;
+    Ok(())
+}
+
+/// Runs the attribute server until the client disconnects.
+async fn serve(
+    ble: &mut Ble<BleConnector<'static>>,
+    control_sender: &Sender<'static, CriticalSectionRawMutex, WifiControlCmd, 4>,
+) {
+    let session = RefCell::new(Session::new());
+
+    let mut ssid_write = |offset: usize, data: &[u8]| {
+        session.borrow_mut().stage(Field::WifiSsid, offset, data);
+    };
+    let mut password_write = |offset: usize, data: &[u8]| {
+        session
+            .borrow_mut()
+            .stage(Field::WifiPassword, offset, data);
+    };
+    let mut url_read =
+        |offset: usize, data: &mut [u8]| read_at(config::get().api_url.as_bytes(), offset, data);
+    let mut url_write = |offset: usize, data: &[u8]| {
+        session.borrow_mut().stage(Field::ApiUrl, offset, data);
+    };
+    let mut status_read = |offset: usize, data: &mut [u8]| {
+        let status = serde_json::to_vec(&status::snapshot()).unwrap_or_default();
+        read_at(&status, offset, data)
+    };
+    let mut control_read = |offset: usize, data: &mut [u8]| {
+        read_at(session.borrow().last_result.as_bytes(), offset, data)
+    };
+    let mut control_write = |_offset: usize, data: &[u8]| {
+        session.borrow_mut().control(data, control_sender);
+    };
+    let mut unlock_write = |offset: usize, data: &[u8]| {
+        session.borrow_mut().unlock(offset, data);
+    };
+
+    gatt!([service {
+        uuid: "7d6e0000-5b1c-4f7a-9c2e-3a1f0b8d4e60",
+        characteristics: [
+            characteristic {
+                uuid: "7d6e0001-5b1c-4f7a-9c2e-3a1f0b8d4e60",
+                write: ssid_write,
+            },
+            characteristic {
+                uuid: "7d6e0002-5b1c-4f7a-9c2e-3a1f0b8d4e60",
+                write: password_write,
+            },
+            characteristic {
+                uuid: "7d6e0003-5b1c-4f7a-9c2e-3a1f0b8d4e60",
+                read: url_read,
+                write: url_write,
+            },
+            characteristic {
+                uuid: "7d6e0004-5b1c-4f7a-9c2e-3a1f0b8d4e60",
+                read: status_read,
+            },
+            characteristic {
+                uuid: "7d6e0005-5b1c-4f7a-9c2e-3a1f0b8d4e60",
+                read: control_read,
+                write: control_write,
+            },
+            characteristic {
+                uuid: "7d6e0006-5b1c-4f7a-9c2e-3a1f0b8d4e60",
+                write: unlock_write,
+            },
+        ],
+    },]);
+
+    let mut rng = bleps::no_rng::NoRng;
+    let mut server = AttributeServer::new(ble, &mut gatt_attributes, &mut rng);
+
+    // Nothing is pushed to clients, status is polled.
+    let mut notifier = || core::future::pending::<NotificationData>();
+
+    if let Err(e) = server.run(&mut notifier).await {
+        error!("BLE attribute server failed: {:?}", e);
+    }
+}
@@ -0,0 +1,3 @@
+pub use trailsense_core::clock::*;
+
+pub mod sntp;
@@ -0,0 +1,141 @@
+use embassy_net::{
+    IpEndpoint, Stack,
+    dns::DnsQueryType,
+    udp::{PacketMetadata, UdpSocket},
+};
+use embassy_time::{Duration, Instant, Timer, WithTimeout};
+use log::{error, info, warn};
+
+use crate::{clock, config};
+
+const NTP_PORT: u16 = 123;
+const NTP_PACKET_LEN: usize = 48;
+/// Seconds from the NTP epoch (1900) to the Unix epoch (1970).
+const NTP_UNIX_DELTA_SECS: u64 = 2_208_988_800;
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(5);
+const RESYNC_INTERVAL: Duration = Duration::from_secs(60 * 60);
+const RETRY_DELAY: Duration = Duration::from_secs(30);
+
+#[derive(Debug)]
+pub enum SntpError {
+    Dns,
+    Socket,
+    Timeout,
+    InvalidResponse,
+}
+
+/// # SNTP Task
+///
+/// Keeps the UTC offset in `clock` up to date, resyncing hourly and retrying sooner while no server answers.
+#[embassy_executor::task]
+pub async fn sntp_task(stack: Stack<'static>) {
+    loop {
+        stack.wait_config_up().await;
+
+        let config = config::get();
+        let ntp_server = &config.ntp_server;
+        let delay = match query_offset_us(stack, ntp_server).await {
+            Ok(offset_us) => {
+                if let Some(previous) = clock::unix_now_us() {
+                    let step_us = (Instant::now().as_micros() as i64 + offset_us) - previous as i64;
+                    info!("SNTP resync, clock stepped by {} ms", step_us / 1000);
+                } else {
+                    info!("SNTP time acquired from '{}'", ntp_server);
+                }
+                clock::set_unix_offset_us(offset_us);
+                RESYNC_INTERVAL
+            }
+            Err(e) => {
+                warn!("SNTP query to '{}' failed: {:?}", ntp_server, e);
+                RETRY_DELAY
+            }
+        };
+
+        Timer::after(delay).await;
+    }
+}
+
+/// Sends a single client request and returns the Unix time at `Instant` zero in microseconds.
+async fn query_offset_us(stack: Stack<'static>, ntp_server: &str) -> Result<i64, SntpError> {
+    let addrs = stack
+        .dns_query(ntp_server, DnsQueryType::A)
+        .await
+        .map_err(|_| SntpError::Dns)?;
+    let addr = *addrs.first().ok_or(SntpError::Dns)?;
+    let server = IpEndpoint::new(addr, NTP_PORT);
+
+    let mut rx_meta = [PacketMetadata::EMPTY; 1];
+    let mut rx_buffer = [0u8; NTP_PACKET_LEN * 2];
+    let mut tx_meta = [PacketMetadata::EMPTY; 1];
+    let mut tx_buffer = [0u8; NTP_PACKET_LEN * 2];
+    let mut socket = UdpSocket::new(
+        stack,
+        &mut rx_meta,
+        &mut rx_buffer,
+        &mut tx_meta,
+        &mut tx_buffer,
+    );
+    socket.bind(0).map_err(|_| SntpError::Socket)?;
+
+    // LI = 0, VN = 4, Mode = 3 (client). The local send time goes into the transmit timestamp,
+    // servers echo it back as the originate timestamp which ties the response to this request.
+    let t1 = Instant::now().as_micros();
+    let mut request = [0u8; NTP_PACKET_LEN];
+    request[0] = 0b00_100_011;
+    request[40..48].copy_from_slice(&t1.to_be_bytes());
+    socket
+        .send_to(&request, server)
+        .await
+        .map_err(|_| SntpError::Socket)?;
+
+    let mut response = [0u8; NTP_PACKET_LEN];
+    let (len, meta) = loop {
+        let (len, meta) = socket
+            .recv_from(&mut response)
+            .with_timeout(RESPONSE_TIMEOUT)
+            .await
+            .map_err(|_| SntpError::Timeout)?
+            .map_err(|_| SntpError::Socket)?;
+        if meta.endpoint == server {
+            break (len, meta);
+        }
+    };
+    let t4 = Instant::now().as_micros();
+    if len < NTP_PACKET_LEN {
+        error!("Short SNTP response from {}: {} bytes", meta.endpoint, len);
+        return Err(SntpError::InvalidResponse);
+    }
+
+    let leap = response[0] >> 6;
+    let mode = response[0] & 0b111;
+    let stratum = response[1];
+    // Leap indicator 3 and stratum 0 (kiss-o'-death) mean the server is not synchronized.
+    if mode != 4 || leap == 3 || stratum == 0 || read_u64(&response, 24) != t1 {
+        return Err(SntpError::InvalidResponse);
+    }
+
+    let t2 = ntp_to_unix_us(read_u64(&response, 32));
+    let t3 = ntp_to_unix_us(read_u64(&response, 40));
+
+    // Standard SNTP offset, ((t2 - t1) + (t3 - t4)) / 2, which cancels a symmetric network delay.
+    Ok(((t2 as i64 - t1 as i64) + (t3 as i64 - t4 as i64)) / 2)
+}
+
+fn read_u64(packet: &[u8], offset: usize) -> u64 {
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&packet[offset..offset + 8]);
+    u64::from_be_bytes(bytes)
+}
+
+/// Converts a 32.32 fixed point NTP timestamp to Unix microseconds.
+/// Seconds below the Unix epoch are taken as NTP era 1, which starts in 2036.
+fn ntp_to_unix_us(timestamp: u64) -> u64 {
+    let ntp_secs = timestamp >> 32;
+    let secs = if ntp_secs >= NTP_UNIX_DELTA_SECS {
+        ntp_secs - NTP_UNIX_DELTA_SECS
+    } else {
+        ntp_secs + (1 << 32) - NTP_UNIX_DELTA_SECS
+    };
+    let micros = ((timestamp & 0xFFFF_FFFF) * 1_000_000) >> 32;
+    secs * 1_000_000 + micros
+}
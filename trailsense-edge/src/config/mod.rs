@@ -0,0 +1,112 @@
+use core::cell::RefCell;
+
+use embassy_sync::blocking_mutex::{Mutex, raw::CriticalSectionRawMutex};
+use esp_hal::efuse::Efuse;
+use log::{error, info, warn};
+
+use crate::{
+    packages::package_store,
+    probes::{filter, probe_parser, rssi},
+    storage::FlashPartition,
+    wifi::manager,
+};
+
+use trailsense_core::config::store::ConfigStore;
+pub use trailsense_core::config::*;
+
+// Every upload is signed with the device secret and the backend rejects unsigned ones,
+// a node flashed without it would count and buffer but never upload.
+const _: () = assert!(
+    matches!(option_env!("TRAILSENSE_DEVICE_SECRET"), Some(secret) if !secret.is_empty()),
+    "TRAILSENSE_DEVICE_SECRET must be set to build the firmware"
+);
+
+fn with_device_password(mut config: Config) -> Config {
+    if config.portal_password.is_empty() {
+        config.portal_password =
+            device_password(config.device_secret.as_bytes(), &Efuse::mac_address())
+                .unwrap_or_default();
+    }
+    config
+}
+
+static STORE: Mutex<CriticalSectionRawMutex, RefCell<Option<ConfigStore<FlashPartition>>>> =
+    Mutex::new(RefCell::new(None));
+
+/// Loads the config from `partition`, writing the defaults on first boot.
+/// Without a partition, or when the stored config is unusable, the defaults are used for this boot.
+pub fn init(partition: Option<FlashPartition>) {
+    let config = match partition.map(ConfigStore::open) {
+        Some(Ok((mut store, payload))) => {
+            let loaded = payload.map(|p| {
+                serde_json::from_slice::<Config>(&p)
+                    .map_err(|_| ConfigError::Encode)
+                    .and_then(check_version)
+                    .map(with_device_password)
+                    .and_then(|c| c.validate().map(|_| c))
+            });
+            let config = match loaded {
+                Some(Ok(config)) => config,
+                Some(Err(e)) => {
+                    error!("Stored config rejected, using defaults: {:?}", e);
+                    Config::default()
+                }
+                None => {
+                    info!("No stored config, writing defaults");
+                    let config = Config::default();
+                    if let Err(e) = save(&mut store, &config) {
+                        error!("Failed to store default config: {:?}", e);
+                    }
+                    config
+                }
+            };
+            STORE.lock(|s| s.replace(Some(store)));
+            config
+        }
+        Some(Err(e)) => {
+            error!("Failed to open config store, using defaults: {:?}", e);
+            Config::default()
+        }
+        None => {
+            warn!("No config partition, using defaults");
+            Config::default()
+        }
+    };
+
+    let config = with_device_password(config);
+    apply(&config);
+    set(config);
+}
+
+/// Validates and persists `config`, then makes it current.
+pub fn update(config: Config) -> Result<(), ConfigError> {
+    config.validate()?;
+    // The store is taken out for the write, an erase must not run inside the critical section.
+    // Updates come from tasks and never yield in between, so no other update finds it missing.
+    match STORE.lock(|s| s.borrow_mut().take()) {
+        Some(mut store) => {
+            let saved = save(&mut store, &config);
+            STORE.lock(|s| s.replace(Some(store)));
+            saved?;
+        }
+        None => warn!("No config store, update only lasts until reboot"),
+    }
+    apply(&config);
+    set(config);
+    info!("Config updated");
+    Ok(())
+}
+
+fn save(store: &mut ConfigStore<FlashPartition>, config: &Config) -> Result<(), ConfigError> {
+    let payload = serde_json::to_vec(config).map_err(|_| ConfigError::Encode)?;
+    store.save(&payload).map_err(ConfigError::Storage)
+}
+
+/// Pushes settings into modules that keep their own copy.
+fn apply(config: &Config) {
+    package_store::configure_eviction(config.eviction());
+    filter::configure(&config.mac_filter);
+    probe_parser::configure(config.fingerprint_method);
+    rssi::configure(config.rssi_min_dbm, config.rssi_max_dbm);
+    manager::configure_hopping(&config.hop_channels, config.hop_dwell());
+}
@@ -1,6 +1,11 @@
-#![no_std]
+#![cfg_attr(not(test), no_std)]
 
+pub mod ble;
+pub mod clock;
+pub mod config;
 pub mod network;
 pub mod packages;
 pub mod probes;
+pub mod status;
+pub mod storage;
 pub mod wifi;
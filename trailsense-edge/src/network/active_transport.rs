@@ -1,32 +1,42 @@
-extern crate alloc;
-use alloc::vec::Vec;
-
+#[cfg(feature = "uplink-gsm")]
+use crate::network::gsm::{GsmUart, transport::GsmTransport};
 #[cfg(feature = "uplink-wifi")]
 use crate::network::wifi::transport::WifiTransport;
 use crate::{
     network::{
         UplinkTransport,
+        failover::FailoverTransport,
         types::{ConnectionOutcome, SendDataOutcome},
     },
     packages::package_store::PackageEntity,
 };
 
-#[cfg(feature = "uplink-wifi")]
+/// The uplinks enabled at build time, ordered by priority.
+pub type TransportChain = FailoverTransport<ActiveTransport>;
+
 pub enum ActiveTransport {
+    #[cfg(feature = "uplink-wifi")]
     Wifi(WifiTransport),
+    #[cfg(feature = "uplink-gsm")]
+    Gsm(GsmTransport<GsmUart>),
 }
 
-#[cfg(feature = "uplink-wifi")]
 impl UplinkTransport for ActiveTransport {
     async fn ensure_connected(&mut self) -> ConnectionOutcome {
         match self {
+            #[cfg(feature = "uplink-wifi")]
             ActiveTransport::Wifi(t) => t.ensure_connected().await,
+            #[cfg(feature = "uplink-gsm")]
+            ActiveTransport::Gsm(t) => t.ensure_connected().await,
         }
     }
 
-    async fn send_data(&mut self, packages: Vec<PackageEntity>) -> SendDataOutcome {
+    async fn send_data(&mut self, packages: &[PackageEntity]) -> SendDataOutcome {
         match self {
+            #[cfg(feature = "uplink-wifi")]
             ActiveTransport::Wifi(t) => t.send_data(packages).await,
+            #[cfg(feature = "uplink-gsm")]
+            ActiveTransport::Gsm(t) => t.send_data(packages).await,
         }
     }
 }
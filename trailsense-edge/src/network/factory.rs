@@ -1,12 +1,21 @@
-use crate::network::active_transport::ActiveTransport;
+extern crate alloc;
+use alloc::vec::Vec;
+
+#[cfg(feature = "uplink-gsm")]
+use crate::network::gsm::GsmUart;
+use crate::network::{
+    active_transport::{ActiveTransport, TransportChain},
+    failover::{FailoverConfig, FailoverTransport},
+};
 #[cfg(feature = "uplink-wifi")]
 use crate::wifi::WifiCtx;
 #[cfg(feature = "uplink-wifi")]
 use crate::wifi::tasks::WifiControlCmd;
+#[cfg(feature = "uplink-wifi")]
 use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, channel::Sender};
 
 #[cfg(feature = "uplink-wifi")]
-pub fn build_active_transport(
+pub fn build_wifi_transport(
     ctx: WifiCtx,
     wifi_control_sender: Sender<'static, CriticalSectionRawMutex, WifiControlCmd, 4>,
 ) -> ActiveTransport {
@@ -15,3 +24,17 @@ pub fn build_active_transport(
     let config = WifiTransportConfig::default();
     return ActiveTransport::Wifi(WifiTransport::new(ctx, config, wifi_control_sender));
 }
+
+#[cfg(feature = "uplink-gsm")]
+pub fn build_gsm_transport(gsm_uart: GsmUart) -> ActiveTransport {
+    use crate::network::gsm::transport::{GsmTransport, GsmTransportConfig};
+
+    let config = GsmTransportConfig::default();
+    return ActiveTransport::Gsm(GsmTransport::new(gsm_uart, config));
+}
+
+/// `links` are tried in order, so pass the preferred uplink first.
+pub fn build_active_transport(links: Vec<ActiveTransport>) -> TransportChain {
+    let config = FailoverConfig::default();
+    return FailoverTransport::new(links, config);
+}
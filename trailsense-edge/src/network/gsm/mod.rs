@@ -0,0 +1,3 @@
+pub use trailsense_core::network::gsm::{at, transport};
+
+pub type GsmUart = esp_hal::uart::Uart<'static, esp_hal::Async>;
@@ -1,17 +1,11 @@
-extern crate alloc;
-use crate::{
-    network::types::{ConnectionOutcome, SendDataOutcome},
-    packages::package_store::PackageEntity,
+pub use trailsense_core::network::{
+    UplinkTransport, acknowledged_seqs, auth, chunk_len, encode_payload, failover, types,
 };
-use alloc::vec::Vec;
+
 pub mod active_transport;
 pub mod factory;
-pub mod types;
+#[cfg(feature = "uplink-gsm")]
+pub mod gsm;
+pub mod tls;
 pub mod uploader;
 pub mod wifi;
-
-#[allow(async_fn_in_trait)]
-pub trait UplinkTransport {
-    async fn send_data(&mut self, packages: Vec<PackageEntity>) -> SendDataOutcome;
-    async fn ensure_connected(&mut self) -> ConnectionOutcome;
-}
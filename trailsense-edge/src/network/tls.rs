@@ -0,0 +1,75 @@
+use embedded_tls::{
+    Aes128GcmSha256, CryptoProvider, CryptoRng, NoClock, TlsError, TlsVerifier, pki::CertVerifier,
+};
+use esp_hal::rng::Rng;
+use rand_core::RngCore;
+
+pub use trailsense_core::network::tls::*;
+
+/// Largest server certificate the verifier keeps for the CertificateVerify check.
+const MAX_CERT_LEN: usize = 4096;
+
+/// Whether the handshake failed because the server could not prove its identity,
+/// as opposed to a network or protocol problem.
+pub fn is_verification_error(error: &TlsError) -> bool {
+    matches!(
+        error,
+        TlsError::InvalidCertificate
+            | TlsError::InvalidCertificateEntry
+            | TlsError::InvalidSignature
+            | TlsError::InvalidSignatureScheme
+    )
+}
+
+/// The hardware RNG, fed by the radio noise while Wi-Fi is up.
+pub struct HardwareRng(Rng);
+
+impl RngCore for HardwareRng {
+    fn next_u32(&mut self) -> u32 {
+        self.0.random()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0.random() as u64 | ((self.0.random() as u64) << 32)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.0.read(dest);
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand_core::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+impl CryptoRng for HardwareRng {}
+
+/// Checks the server chain against the CA of the `TlsConfig` and the hostname against its server name.
+/// There is no clock, certificate validity periods are not checked.
+pub struct VerifyingProvider {
+    rng: HardwareRng,
+    verifier: CertVerifier<Aes128GcmSha256, NoClock, MAX_CERT_LEN>,
+}
+
+impl Default for VerifyingProvider {
+    fn default() -> Self {
+        Self {
+            rng: HardwareRng(Rng::new()),
+            verifier: CertVerifier::new(),
+        }
+    }
+}
+
+impl CryptoProvider for VerifyingProvider {
+    type CipherSuite = Aes128GcmSha256;
+    type Signature = &'static [u8];
+
+    fn rng(&mut self) -> impl embedded_tls::CryptoRngCore {
+        &mut self.rng
+    }
+
+    fn verifier(&mut self) -> Result<&mut impl TlsVerifier<Self::CipherSuite>, TlsError> {
+        Ok(&mut self.verifier)
+    }
+}
@@ -1,92 +1,180 @@
 extern crate alloc;
-use crate::network::{UplinkTransport, types::ConnectionOutcome};
+use crate::network::{UplinkTransport, auth, chunk_len, types::ConnectionOutcome};
+use alloc::vec::Vec;
 use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, channel::Sender};
-use embassy_time::{Duration, Timer, WithTimeout};
+use embassy_time::{Instant, Timer, WithTimeout};
 use log::{error, info};
 
 use crate::{
-    network::{active_transport::ActiveTransport, types::SendDataOutcome},
+    clock, config,
+    network::{active_transport::TransportChain, types::SendDataOutcome},
     packages::package_store,
-    probes::{counter, fingerprint_store},
-    wifi::manager::WifiCmd,
+    probes::{counter, coverage, fingerprint_store, global_macs},
+    status,
+    wifi::manager::{self, ChannelPlan, WifiCmd},
 };
 
 #[embassy_executor::task]
 pub async fn uploader_task(
-    mut transport: ActiveTransport,
+    mut transport: TransportChain,
     wifi_command_sender: Sender<'static, CriticalSectionRawMutex, WifiCmd, 4>,
 ) {
-    const PERIOD: Duration = Duration::from_secs(20);
-    const SEND_TIMEOUT: Duration = Duration::from_secs(30);
-    const RETRY_DELAY: Duration = Duration::from_millis(500);
-    const RADIO_SETTLE_DELAY: Duration = Duration::from_secs(5);
-    const SEND_ATTEMPTS: u8 = 5;
+    const MAX_CHUNK_ENTRIES: usize = 16;
+    const MAX_CHUNK_BYTES: usize = 2048;
 
+    // Sniffing stays on through uploads, the station shares the radio on its access point's channel.
     wifi_command_sender.send(WifiCmd::StartSniffing).await;
+    let mut window_start = Instant::now();
+    let mut channel_plan = None;
 
     loop {
-        Timer::after(PERIOD).await;
+        let config = config::get();
+        let period = config.upload_period();
 
+        let wanted_plan = if config.channel_hopping {
+            ChannelPlan::Hopping
+        } else {
+            ChannelPlan::Fixed
+        };
+        if channel_plan != Some(wanted_plan) {
+            wifi_command_sender
+                .send(WifiCmd::SetChannelPlan(wanted_plan))
+                .await;
+            channel_plan = Some(wanted_plan);
+        }
+
+        // Once the clock is synced windows end on UTC multiples of the period, a boundary closer than half
+        // a period is skipped so the upload time never leaves a stub window.
+        let mut until_boundary = clock::until_next_boundary(period);
+        if until_boundary < period / 2 {
+            until_boundary += period;
+        }
+        Timer::after(until_boundary).await;
+
+        // The window closes on the boundary whether or not the uplink is up, a failed connect only delays
+        // its upload and never stretches it over several periods.
+        let fingerprint_snapshot = fingerprint_store::snapshot();
+        let randomized_count = counter::deduplicate_probes(
+            &fingerprint_snapshot,
+            &counter::bit_weights(config.fingerprint_method),
+            config.dedup_threshold(),
+        );
+        package_store::push(
+            global_macs::take_count(),
+            randomized_count,
+            window_start,
+            coverage::take_window(),
+        )
+        .await;
+        fingerprint_store::drain();
+        window_start = Instant::now();
+
+        // Keeps a hopping sniffer on the station's channel until the uploads are done.
+        let radio_claim = manager::claim_radio();
         match transport.ensure_connected().await {
             ConnectionOutcome::Connected => {
                 info!("Connection Established")
             }
             ConnectionOutcome::Failure | ConnectionOutcome::Disconnected => {
-                error!("Connection timeout");
+                error!(
+                    "Connection timeout, keeping {} packages",
+                    package_store::len()
+                );
                 continue;
             }
         }
 
-        let fingerprint_snapshot = fingerprint_store::snapshot();
-        let curr_count = counter::deduplicate_probes(&fingerprint_snapshot);
-        package_store::push(curr_count); // TODO: implement limit to avoid buffer overflow of http request. Basically use chunking.
-        fingerprint_store::drain();
+        // Checked before anything is marked as sent, so the backlog can still be merged while waiting.
+        let signable = auth::can_sign();
+        status::set_uploads_held(signable.err());
+        if let Err(e) = signable {
+            error!(
+                "Cannot sign uploads, keeping {} packages: {:?}",
+                package_store::len(),
+                e
+            );
+            continue;
+        }
 
-        wifi_command_sender.send(WifiCmd::StopSniffing).await;
-        Timer::after(RADIO_SETTLE_DELAY).await;
-
-        let mut ok = false;
-        for attempt in 0..SEND_ATTEMPTS {
-            let packages = package_store::snapshot_with_age();
-
-            match transport
-                .send_data(packages)
-                .with_timeout(SEND_TIMEOUT)
-                .await
-            {
-                Ok(SendDataOutcome::Success) => {
-                    package_store::drain();
-                    ok = true;
-                    break;
-                }
-                Ok(SendDataOutcome::RetryableFailure) => {
-                    error!("Data sending had a retriable failure");
-                }
-                Ok(SendDataOutcome::FatalFailure) => {
-                    error!("HTTP send failed");
-                    ok = false;
-                    break;
+        // Send the backlog oldest first in bounded chunks, draining each one once the server accepted it,
+        // so a failure part way through neither loses nor resends the chunks before it.
+        let mut ok = true;
+        let mut sent_chunks = 0;
+        'chunks: loop {
+            let chunk_len = chunk_len(
+                &package_store::snapshot_with_age(),
+                MAX_CHUNK_ENTRIES,
+                MAX_CHUNK_BYTES,
+            );
+            if chunk_len == 0 {
+                break;
+            }
+
+            for attempt in 0..config.send_attempts {
+                let mut packages = package_store::snapshot_with_age();
+                packages.truncate(chunk_len);
+                let seqs: Vec<u32> = packages.iter().map(|p| p.seq).collect();
+                package_store::mark_sent(&seqs).await;
+
+                match transport
+                    .send_data(&packages)
+                    .with_timeout(config.send_timeout())
+                    .await
+                {
+                    Ok(SendDataOutcome::Success(acknowledged)) => {
+                        let before = package_store::len();
+                        package_store::drain_acknowledged(&acknowledged).await;
+                        // A chunk the server refuses entirely would otherwise be resent forever.
+                        if package_store::len() == before {
+                            error!(
+                                "Server acknowledged none of the {} packages sent",
+                                chunk_len
+                            );
+                            break;
+                        }
+                        sent_chunks += 1;
+                        continue 'chunks;
+                    }
+                    Ok(SendDataOutcome::RetryableFailure) => {
+                        error!("Data sending had a retriable failure");
+                    }
+                    Ok(SendDataOutcome::FatalFailure) => {
+                        error!("HTTP send failed");
+                        break;
+                    }
+                    Ok(SendDataOutcome::TlsVerificationFailed) => {
+                        error!("Server certificate verification failed, keeping packages");
+                        break;
+                    }
+                    Ok(SendDataOutcome::BackoffRequired) => {
+                        info!(
+                            "Transport recovery/backoff in progress; skipping remaining attempts this cycle"
+                        );
+                        break;
+                    }
+                    Err(_) => error!("Package sending timed out"),
                 }
-                Ok(SendDataOutcome::BackoffRequired) => {
-                    info!(
-                        "Transport recovery/backoff in progress; skipping remaining attempts this cycle"
-                    );
-                    break;
+
+                if attempt + 1 < config.send_attempts {
+                    Timer::after(config.send_retry_delay()).await;
                 }
-                Err(_) => error!("Package sending timed out"),
             }
 
-            if attempt + 1 < SEND_ATTEMPTS {
-                Timer::after(RETRY_DELAY).await;
-            }
+            ok = false;
+            break;
         }
 
-        wifi_command_sender.send(WifiCmd::StartSniffing).await;
+        drop(radio_claim);
+        status::record_upload(ok, sent_chunks);
 
         if ok {
-            info!("Package sent successfully");
+            info!("Package sent successfully ({} chunks)", sent_chunks);
         } else {
-            error!("Package sending failed");
+            error!(
+                "Package sending failed after {} accepted chunks, {} packages left",
+                sent_chunks,
+                package_store::len()
+            );
         }
     }
 }
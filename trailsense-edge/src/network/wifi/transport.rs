@@ -1,5 +1,4 @@
-extern crate alloc;
-use alloc::vec::Vec;
+use core::net::SocketAddr;
 
 use embassy_net::{
     Stack,
@@ -7,35 +6,30 @@ use embassy_net::{
     tcp::client::{TcpClient, TcpClientState},
 };
 use embassy_time::{Duration, Timer, WithTimeout};
-use log::{error, info};
+use embedded_io_async::{Read, Write};
+use embedded_nal_async::{AddrType, Dns, TcpConnect};
+use embedded_tls::{Aes128GcmSha256, Certificate, TlsConfig, TlsConnection, TlsContext, TlsError};
+use log::{error, info, warn};
 use reqwless::{
-    client::{HttpClient, TlsConfig},
-    request::RequestBuilder,
+    client::HttpConnection,
+    headers::ContentType,
+    request::{Request, RequestBuilder},
 };
 
 use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, channel::Sender};
 
 use crate::{
+    config,
     network::{
-        UplinkTransport,
-        types::{ConnectionOutcome, PackageDto, SendDataOutcome},
+        UplinkTransport, acknowledged_seqs, auth, encode_payload, tls,
+        types::{ConnectionOutcome, SendDataOutcome},
     },
     packages::package_store::PackageEntity,
     wifi::{WifiCtx, tasks::WifiControlCmd, wait_for_connection},
 };
 
-const BASE_URL: &str = match option_env!("TRAILSENSE_API_URL") {
-    Some(v) => v,
-    None => "https://api.trailsense.daugt.com",
-};
-
-const DEVICE_ID: &str = match option_env!("TRAILSENSE_EDGE_ID") {
-    Some(v) => v,
-    None => "71ec4873-944e-49c1-b7c4-4b856797715f",
-};
-
-const REQUEST_BUILD_ATTEMPTS: u8 = 3;
-const REQUEST_RETRY_DELAY: Duration = Duration::from_millis(750);
+const CONNECT_ATTEMPTS: u8 = 3;
+const CONNECT_RETRY_DELAY: Duration = Duration::from_millis(750);
 
 pub struct WifiTransportConfig {
     pub dns_reconnect_threshold: u8,
@@ -44,16 +38,18 @@ pub struct WifiTransportConfig {
 
 impl Default for WifiTransportConfig {
     fn default() -> Self {
+        let config = config::get();
         Self {
-            dns_reconnect_threshold: 2,
-            dns_restart_threshold: 4,
+            dns_reconnect_threshold: config.dns_reconnect_threshold,
+            dns_restart_threshold: config.dns_restart_threshold,
         }
     }
 }
 
 pub struct WifiTransport {
     stack: Stack<'static>,
-    tls_seed: u64,
+    /// Trust anchor that verified the server last, the first one tried.
+    anchor: usize,
     dns_reconnect_threshold: u8,
     consecutive_dns_failures: u8,
     dns_restart_threshold: u8,
@@ -69,7 +65,7 @@ impl WifiTransport {
     ) -> Self {
         WifiTransport {
             stack: context.stack,
-            tls_seed: context.tls_seed,
+            anchor: 0,
             consecutive_dns_failures: 0,
             recovery_pending: false,
             dns_reconnect_threshold: config.dns_reconnect_threshold,
@@ -102,7 +98,7 @@ impl UplinkTransport for WifiTransport {
 
         ConnectionOutcome::Connected
     }
-    async fn send_data(&mut self, packages: Vec<PackageEntity>) -> SendDataOutcome {
+    async fn send_data(&mut self, packages: &[PackageEntity]) -> SendDataOutcome {
         if self.recovery_pending {
             return SendDataOutcome::BackoffRequired;
         }
@@ -123,38 +119,21 @@ impl UplinkTransport for WifiTransport {
             return SendDataOutcome::BackoffRequired;
         }
 
-        let mut rx_buffer = [0; 4096]; // TODO: Refactor to reuse static TLS RX/TX buffers instead of allocating new ones per call, to reduce memory usage on constrained devices.
-        let mut tx_buffer = [0; 4096];
         let mut url = heapless::String::<128>::new();
         use core::fmt::Write;
-        if let Err(e) = write!(&mut url, "{}/ingest", BASE_URL) {
+        if let Err(e) = write!(&mut url, "{}/ingest", config::get().api_url) {
             error!("Failed to generate URL: {}", e);
             self.consecutive_dns_failures = 0;
             return SendDataOutcome::FatalFailure;
         }
 
-        let dns = DnsSocket::new(self.stack);
-        let tcp_state = TcpClientState::<1, 4096, 4096>::new();
-        let tcp = TcpClient::new(self.stack, &tcp_state);
-
-        let tls = TlsConfig::new(
-            self.tls_seed,
-            &mut rx_buffer,
-            &mut tx_buffer,
-            reqwless::client::TlsVerify::None, // TODO: this should be replaced by "Certificate" later on, we need to define the final domain for that.
-        );
-
-        let mut client = HttpClient::new_with_tls(&tcp, &dns, tls);
-
-        let mut buffer = [0u8; 4096];
-
-        let payload: Vec<PackageDto<'_>> = packages
-            .iter()
-            .map(|p| PackageDto::new(p.age_in_seconds, p.count, DEVICE_ID))
-            .inspect(|dto| info!("Package: {:?}", dto))
-            .collect();
+        if !tls::scheme_allowed(&url) {
+            error!("Refusing to upload to '{}' without TLS", url.as_str());
+            self.consecutive_dns_failures = 0;
+            return SendDataOutcome::FatalFailure;
+        }
 
-        let body = match serde_json::to_vec(&payload) {
+        let body = match encode_payload(packages) {
             Ok(v) => v,
             Err(e) => {
                 error!("Failed to serialize payload: {:?}", e);
@@ -163,80 +142,139 @@ impl UplinkTransport for WifiTransport {
             }
         };
 
-        let request_builder = 'request: loop {
-            for attempt in 0..REQUEST_BUILD_ATTEMPTS {
-                match client
-                    .request(reqwless::request::Method::POST, url.as_str())
+        let auth = match auth::sign_request(&body) {
+            Ok(auth) => auth,
+            Err(e) => {
+                error!("Cannot sign ingest request, holding packages back: {:?}", e);
+                return SendDataOutcome::BackoffRequired;
+            }
+        };
+        let headers = auth.pairs();
+
+        self.post(&url, &headers, &body, packages).await
+    }
+}
+
+impl WifiTransport {
+    async fn post(
+        &mut self,
+        url: &str,
+        headers: &[(&str, &str)],
+        body: &[u8],
+        packages: &[PackageEntity],
+    ) -> SendDataOutcome {
+        let Some(endpoint) = tls::Endpoint::parse(url) else {
+            error!("Invalid upload URL '{}'", url);
+            self.consecutive_dns_failures = 0;
+            return SendDataOutcome::FatalFailure;
+        };
+
+        let dns = DnsSocket::new(self.stack);
+        let address = match dns.get_host_by_name(endpoint.host, AddrType::Either).await {
+            Ok(address) => address,
+            Err(e) => {
+                error!("DNS lookup of '{}' failed: {:?}", endpoint.host, e);
+                self.consecutive_dns_failures += 1;
+                return SendDataOutcome::RetryableFailure;
+            }
+        };
+        self.consecutive_dns_failures = 0;
+        let remote = SocketAddr::new(address, endpoint.port);
+
+        let tcp_state = TcpClientState::<1, 4096, 4096>::new();
+        let tcp = TcpClient::new(self.stack, &tcp_state);
+        let mut rx_buffer = [0; 4096]; // TODO: Refactor to reuse static TLS RX/TX buffers instead of allocating new ones per call, to reduce memory usage on constrained devices.
+        let mut tx_buffer = [0; 4096];
+
+        let mut connection = if endpoint.https {
+            // embedded-tls takes a single CA, so the handshake is what picks the anchor of a bundle.
+            // It starts with the one that verified the server last, the request itself is only sent once.
+            let anchors = tls::trust_anchors();
+            let verified = 'handshake: {
+                for offset in 0..anchors.len() {
+                    let index = (self.anchor + offset) % anchors.len();
+                    let Some(socket) = connect(&tcp, remote).await else {
+                        return SendDataOutcome::FatalFailure;
+                    };
+                    match handshake(
+                        socket,
+                        endpoint.host,
+                        &anchors[index],
+                        &mut rx_buffer,
+                        &mut tx_buffer,
+                    )
                     .await
-                {
-                    Ok(builder) => break 'request builder,
-                    Err(e) => {
-                        error!(
-                            "Failed to build HTTP request: url='{}', attempt {}/{}, err={:?}",
-                            url.as_str(),
-                            attempt + 1,
-                            REQUEST_BUILD_ATTEMPTS,
-                            e
-                        );
-                        if matches!(e, reqwless::Error::Dns) {
-                            self.consecutive_dns_failures += 1;
-                            return SendDataOutcome::RetryableFailure;
+                    {
+                        Ok(session) => {
+                            self.anchor = index;
+                            break 'handshake Some(session);
                         }
-                        if attempt + 1 < REQUEST_BUILD_ATTEMPTS {
-                            Timer::after(REQUEST_RETRY_DELAY).await;
+                        Err(e) if tls::is_verification_error(&e) => warn!(
+                            "Server certificate not verified by trust anchor {}/{}",
+                            index + 1,
+                            anchors.len()
+                        ),
+                        Err(e) => {
+                            error!("TLS handshake with '{}' failed: {:?}", endpoint.host, e);
+                            return SendDataOutcome::FatalFailure;
                         }
                     }
                 }
-            }
-            self.consecutive_dns_failures = 0;
-            return SendDataOutcome::FatalFailure;
+                None
+            };
+            let Some(session) = verified else {
+                error!("No trust anchor verifies the server, keeping packages");
+                return SendDataOutcome::TlsVerificationFailed;
+            };
+            HttpConnection::Tls(session)
+        } else {
+            let Some(socket) = connect(&tcp, remote).await else {
+                return SendDataOutcome::FatalFailure;
+            };
+            HttpConnection::Plain(socket)
         };
 
-        let mut http_req = request_builder
-            .content_type(reqwless::headers::ContentType::ApplicationJson)
-            .body(body.as_slice());
-
-        let response = match http_req.send(&mut buffer).await {
+        let request = Request::post(endpoint.path)
+            .host(endpoint.host)
+            .headers(headers)
+            .content_type(ContentType::ApplicationJson)
+            .body(body)
+            .build();
+        let mut buffer = [0u8; 4096];
+        let response = match connection.send(request, &mut buffer).await {
             Ok(r) => r,
             Err(e) => {
                 error!(
                     "HTTP POST send failed: url='{}', payload_len={}, err={:?}",
-                    url.as_str(),
+                    url,
                     body.len(),
                     e
                 );
-                if matches!(e, reqwless::Error::Dns) {
-                    self.consecutive_dns_failures += 1;
-                    return SendDataOutcome::RetryableFailure;
-                }
-                self.consecutive_dns_failures = 0;
                 return SendDataOutcome::FatalFailure;
             }
         };
 
         let status = response.status;
-        let body = match response.body().read_to_end().await {
+        let response_body = match response.body().read_to_end().await {
             Ok(b) => b,
             Err(e) => {
                 error!(
                     "HTTP response read failed: url='{}', status={:?}, err={:?}",
-                    url.as_str(),
-                    status,
-                    e
+                    url, status, e
                 );
                 self.consecutive_dns_failures = 0;
                 return SendDataOutcome::FatalFailure;
             }
         };
 
-        let body_content = match core::str::from_utf8(body) {
+        let body_content = match core::str::from_utf8(response_body) {
             Ok(s) => s,
             Err(e) => {
                 error!(
                     "HTTP response UTF-8 decode failed: url='{}', status={:?}, body_len={}, err={:?}",
-                    url.as_str(),
+                    url,
                     status,
-                    body.len(),
+                    response_body.len(),
                     e
                 );
                 self.consecutive_dns_failures = 0;
@@ -247,7 +285,7 @@ impl UplinkTransport for WifiTransport {
         if status.is_successful() {
             info!("Success ({:?}): {}", status, body_content);
             self.consecutive_dns_failures = 0;
-            SendDataOutcome::Success
+            SendDataOutcome::Success(acknowledged_seqs(packages, body_content))
         } else {
             error!("Error ({:?}): {}", status, body_content);
             self.consecutive_dns_failures = 0;
@@ -255,3 +293,43 @@ impl UplinkTransport for WifiTransport {
         }
     }
 }
+
+/// Opens a TCP connection, retrying attempts that were refused or dropped.
+async fn connect<T: TcpConnect>(tcp: &T, remote: SocketAddr) -> Option<T::Connection<'_>> {
+    for attempt in 0..CONNECT_ATTEMPTS {
+        match tcp.connect(remote).await {
+            Ok(socket) => return Some(socket),
+            Err(e) => {
+                error!(
+                    "Failed to connect to {}, attempt {}/{}, err={:?}",
+                    remote,
+                    attempt + 1,
+                    CONNECT_ATTEMPTS,
+                    e
+                );
+                if attempt + 1 < CONNECT_ATTEMPTS {
+                    Timer::after(CONNECT_RETRY_DELAY).await;
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Opens TLS on `socket`, the server has to prove it is `host` with a chain leading to `anchor`.
+async fn handshake<'a, S: Read + Write + 'a>(
+    socket: S,
+    host: &str,
+    anchor: &[u8],
+    rx_buffer: &'a mut [u8],
+    tx_buffer: &'a mut [u8],
+) -> Result<TlsConnection<'a, S, Aes128GcmSha256>, TlsError> {
+    let config = TlsConfig::new()
+        .with_server_name(host)
+        .with_ca(Certificate::X509(anchor));
+    let mut session = TlsConnection::new(socket, rx_buffer, tx_buffer);
+    session
+        .open(TlsContext::new(&config, tls::VerifyingProvider::default()))
+        .await?;
+    Ok(session)
+}
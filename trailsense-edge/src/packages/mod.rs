@@ -1 +1,3 @@
+pub use trailsense_core::packages::{downsample, flash_log};
+
 pub mod package_store;
@@ -1,52 +1,213 @@
 extern crate alloc;
 use alloc::vec::Vec;
-use core::cell::RefCell;
-use embassy_sync::blocking_mutex::{Mutex, raw::CriticalSectionRawMutex};
-use embassy_time::Instant;
+use core::cell::{Cell, RefCell};
+use embassy_sync::{
+    blocking_mutex::{Mutex, raw::CriticalSectionRawMutex},
+    mutex::Mutex as AsyncMutex,
+};
+use embassy_time::{Duration, Instant};
+use esp_hal::rng::Rng;
 use heapless::Vec as HeaplessVec;
+use log::{error, info, warn};
 
-#[derive(Debug, Clone)]
-pub struct PackageEntity {
-    pub count: u32,
-    pub age_in_seconds: u64,
-    pub last_seen: Instant,
-}
+use crate::{
+    packages::{
+        downsample::{Eviction, EvictionConfig, plan_eviction},
+        flash_log::{LogMeta, LogRecord, PackageLog, StoredPackage},
+    },
+    probes::coverage::WindowStats,
+    storage::FlashPartition,
+};
+
+pub use trailsense_core::packages::entity::PackageEntity;
+
+const MAX_PACKAGES: usize = 64;
+
+/// A log record together with the buffer as it is after applying it.
+type Change = (LogRecord, Vec<StoredPackage>);
+
+static PACKAGES: Mutex<CriticalSectionRawMutex, RefCell<HeaplessVec<PackageEntity, MAX_PACKAGES>>> =
+    Mutex::new(RefCell::new(HeaplessVec::new()));
+
+static EVICTION: Mutex<CriticalSectionRawMutex, Cell<EvictionConfig>> =
+    Mutex::new(Cell::new(EvictionConfig::DEFAULT));
+
+/// `None` until the log is mounted, or until the first push when running without one.
+static META: Mutex<CriticalSectionRawMutex, Cell<Option<LogMeta>>> = Mutex::new(Cell::new(None));
+
+static BOOT_RTC_US: Mutex<CriticalSectionRawMutex, Cell<u64>> = Mutex::new(Cell::new(0));
+
+/// # Persistence
+///
+/// Held across a whole buffer change, from updating `PACKAGES` to writing the log, so records reach flash
+/// in the order they were applied. The flash writes, and the sector erase a record can trigger, take tens of
+/// milliseconds and happen after `PACKAGES` is released, with interrupts enabled.
+static PERSISTENCE: AsyncMutex<CriticalSectionRawMutex, Option<PackageLog<FlashPartition>>> =
+    AsyncMutex::new(None);
 
-impl PackageEntity {
-    pub fn new(count: u32) -> Self {
-        Self {
-            count,
-            age_in_seconds: 0,
-            last_seen: Instant::now(),
+/// Restores buffered packages from `partition` and persists every later change to it.
+/// `boot_rtc_us` is the RTC time at boot, which keeps running through software, watchdog and brownout resets,
+/// so package ages include the time the node was down. After a full power loss the RTC restarts and the
+/// downtime is unknown, restored ages then only cover the time until the last logged package.
+pub async fn init_persistence(partition: FlashPartition, boot_rtc_us: u64) {
+    let mut persistence = PERSISTENCE.lock().await;
+
+    let (mut log, restored) = match PackageLog::mount(partition) {
+        Ok(v) => v,
+        Err(e) => {
+            error!(
+                "Failed to mount package log, packages are RAM only: {:?}",
+                e
+            );
+            return;
         }
+    };
+
+    let last_alive_us = restored.iter().map(|p| p.created_at_us).max().unwrap_or(0);
+    let rtc_survived = last_alive_us <= boot_rtc_us;
+    if !rtc_survived {
+        warn!("RTC was reset, restored package ages exclude the downtime");
     }
 
-    pub fn update_age(&mut self) {
-        let now = Instant::now();
-        let delta = now.duration_since(self.last_seen);
-        self.age_in_seconds = self.age_in_seconds.saturating_add(delta.as_secs());
-        self.last_seen = now;
+    BOOT_RTC_US.lock(|b| b.set(boot_rtc_us));
+    let live = PACKAGES.lock(|v| {
+        let mut packages = v.borrow_mut();
+        packages.clear();
+        for stored in restored.into_iter().rev().take(MAX_PACKAGES).rev() {
+            let age_us = if rtc_survived {
+                boot_rtc_us - stored.created_at_us
+            } else {
+                last_alive_us - stored.created_at_us
+            };
+            let stored = StoredPackage {
+                // Re-base on the current RTC so later checkpoints stay consistent.
+                created_at_us: boot_rtc_us.saturating_sub(age_us),
+                ..stored
+            };
+            let age_in_seconds = Duration::from_micros(age_us).as_secs();
+            let _ = packages.push(PackageEntity::from_stored(
+                stored,
+                age_in_seconds,
+                Instant::now(),
+            ));
+        }
+        info!("Restored {} buffered packages from flash", packages.len());
+        stored_packages(&packages)
+    });
+
+    let mut meta = log.meta();
+    meta.boot_id = meta.boot_id.wrapping_add(1);
+    if let Err(e) = log.append(&LogRecord::Meta(meta), &live) {
+        error!("Failed to persist boot counter: {:?}", e);
     }
+    META.lock(|m| m.set(Some(meta)));
+    info!(
+        "Boot {}, next package sequence {}",
+        meta.boot_id, meta.next_seq
+    );
+
+    *persistence = Some(log);
 }
 
-const MAX_PACKAGES: usize = 64;
+/// Takes the next sequence number, together with the boot it belongs to.
+fn take_seq() -> LogMeta {
+    META.lock(|m| {
+        let meta = m.get().unwrap_or_else(volatile_meta);
+        m.set(Some(LogMeta {
+            next_seq: meta.next_seq.wrapping_add(1),
+            ..meta
+        }));
+        meta
+    })
+}
 
-static PACKAGES: Mutex<CriticalSectionRawMutex, RefCell<HeaplessVec<PackageEntity, MAX_PACKAGES>>> =
-    Mutex::new(RefCell::new(HeaplessVec::new()));
+/// Counters for a boot without package log. Sequence numbers restart at 0, so the boot id is random
+/// instead of counted, which keeps `(boot_id, seq)` unique across boots for the server. It is drawn at
+/// the first push, when the radio is running and feeds the RNG its entropy.
+fn volatile_meta() -> LogMeta {
+    let boot_id = Rng::new().random();
+    info!("No package log, using random boot id {}", boot_id);
+    LogMeta {
+        next_seq: 0,
+        boot_id,
+    }
+}
 
-pub fn push(count: u32) -> bool {
-    PACKAGES.lock(|v| {
+fn rtc_now_us() -> u64 {
+    BOOT_RTC_US.lock(|b| b.get()) + Instant::now().as_micros()
+}
+
+fn stored_packages(packages: &[PackageEntity]) -> Vec<StoredPackage> {
+    packages.iter().map(PackageEntity::to_stored).collect()
+}
+
+fn persist(log: &mut Option<PackageLog<FlashPartition>>, changes: Vec<Change>) {
+    let Some(log) = log.as_mut() else {
+        return;
+    };
+    for (record, live) in changes {
+        if let Err(e) = log.append(&record, &live) {
+            error!("Failed to persist package change: {:?}", e);
+        }
+    }
+}
+
+pub fn configure_eviction(config: EvictionConfig) {
+    EVICTION.lock(|e| e.set(config));
+}
+
+/// Buffers the counts of a window that opened at `started` and ends now.
+/// When full, old packages are merged into coarser buckets before anything is dropped.
+pub async fn push(
+    global_count: u32,
+    randomized_count: u32,
+    started: Instant,
+    stats: WindowStats,
+) -> bool {
+    let mut persistence = PERSISTENCE.lock().await;
+    let (ok, changes) = PACKAGES.lock(|v| {
         let mut packages = v.borrow_mut();
+        let mut changes = Vec::new();
 
-        // Evict oldest buffered package to keep a bounded queue.
-        // TODO: think about better solution, chunking, deleting values in between? Drop a few 0 count values?
-        // Thought appeared on 20.02.2026 --> If is 0, do delete it, only if enough other values are around it maybe?ß
         if packages.is_full() {
-            packages.remove(0);
+            let config = EVICTION.lock(|e| e.get());
+            match plan_eviction(&packages, &config) {
+                Eviction::Merge(index) => {
+                    let meta = take_seq();
+                    let older = packages.remove(index);
+                    packages[index].merge_older(&older, meta);
+                    let record = LogRecord::Merged {
+                        index,
+                        seq: meta.next_seq,
+                        boot_id: meta.boot_id,
+                    };
+                    changes.push((record, stored_packages(&packages)));
+                }
+                Eviction::Drop(index) => {
+                    warn!("Package buffer full, dropping package {}", index);
+                    packages.remove(index);
+                    changes.push((LogRecord::Remove(index), stored_packages(&packages)));
+                }
+            }
         }
 
-        packages.push(PackageEntity::new(count)).is_ok()
-    })
+        let meta = take_seq();
+        let entity = PackageEntity::new(
+            meta.next_seq,
+            meta.boot_id,
+            global_count,
+            randomized_count,
+            started,
+            rtc_now_us(),
+            stats,
+        );
+        let record = LogRecord::Push(entity.to_stored());
+        let ok = packages.push(entity).is_ok();
+        changes.push((record, stored_packages(&packages)));
+        (ok, changes)
+    });
+    persist(&mut persistence, changes);
+    ok
 }
 
 pub fn snapshot_with_age() -> Vec<PackageEntity> {
@@ -59,6 +220,53 @@ pub fn snapshot_with_age() -> Vec<PackageEntity> {
     })
 }
 
-pub fn drain() {
+pub async fn drain() {
+    let mut persistence = PERSISTENCE.lock().await;
     PACKAGES.lock(|v| v.borrow_mut().clear());
+    persist(
+        &mut persistence,
+        alloc::vec![(LogRecord::Clear, Vec::new())],
+    );
+}
+
+/// Marks the packages about to be handed to a transport, they are never merged afterwards since the
+/// server may store them even when the upload looks failed.
+pub async fn mark_sent(seqs: &[u32]) {
+    let mut persistence = PERSISTENCE.lock().await;
+    let changed = PACKAGES.lock(|v| {
+        let mut packages = v.borrow_mut();
+        let mut changed = false;
+        for package in packages
+            .iter_mut()
+            .filter(|p| !p.sent && seqs.contains(&p.seq))
+        {
+            package.sent = true;
+            changed = true;
+        }
+        changed.then(|| stored_packages(&packages))
+    });
+    if let Some(live) = changed {
+        persist(
+            &mut persistence,
+            alloc::vec![(LogRecord::Sent(seqs.to_vec()), live)],
+        );
+    }
+}
+
+/// Removes the packages the server acknowledged, anything else stays buffered for the next attempt.
+pub async fn drain_acknowledged(seqs: &[u32]) {
+    let mut persistence = PERSISTENCE.lock().await;
+    let live = PACKAGES.lock(|v| {
+        let mut packages = v.borrow_mut();
+        packages.retain(|p| !seqs.contains(&p.seq));
+        stored_packages(&packages)
+    });
+    persist(
+        &mut persistence,
+        alloc::vec![(LogRecord::Ack(seqs.to_vec()), live)],
+    );
+}
+
+pub fn len() -> usize {
+    PACKAGES.lock(|v| v.borrow().len())
 }
@@ -1,4 +1,6 @@
-pub mod counter;
-pub mod fingerprint_store;
-pub mod models;
-pub mod probe_parser;
+pub use trailsense_core::probes::{
+    counter, coverage, features, filter, fingerprint, fingerprint_store, global_macs, models,
+    probe_parser, rssi,
+};
+
+pub mod pipeline;
@@ -0,0 +1,47 @@
+use esp_radio::wifi::PromiscuousPkt;
+use log::warn;
+
+use crate::probes::{probe_parser, rssi::RxMeta};
+
+pub use trailsense_core::probes::pipeline::*;
+
+/// # Capture Packet
+///
+/// Promiscuous receive callback. It runs on the Wi-Fi driver's task for every frame, so it only
+/// counts the frame and copies probe requests into the queue for `probe_task`. A full queue drops the probe,
+/// frames the radio received with errors are dropped before anything else.
+pub fn capture_packet(packet: PromiscuousPkt<'_>) {
+    let rx = RxMeta {
+        rssi: packet.rx_cntl.rssi as i8,
+        channel: packet.rx_cntl.channel as u8,
+        rate: packet.rx_cntl.rate as u8,
+        noise_floor: packet.rx_cntl.noise_floor as i8,
+    };
+    capture(packet.rx_cntl.rx_state != 0, rx, &packet.data);
+}
+
+/// # Probe Task
+///
+/// Parses, fingerprints and stores the probes `capture_packet` queued.
+#[embassy_executor::task]
+pub async fn probe_task() {
+    let mut reported_drops = 0;
+
+    loop {
+        let probe = receive().await;
+        probe_parser::process_frame(&probe.frame, &probe.rx);
+
+        // Reported once the queue drained, so a burst produces one line.
+        if is_drained() {
+            let drops = queue_drops();
+            if drops != reported_drops {
+                warn!(
+                    "Probe queue full, dropped {} probes ({} since boot)",
+                    drops.wrapping_sub(reported_drops),
+                    drops
+                );
+                reported_drops = drops;
+            }
+        }
+    }
+}
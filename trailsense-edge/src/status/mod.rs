@@ -0,0 +1,116 @@
+extern crate alloc;
+use alloc::string::String;
+use core::cell::{Cell, RefCell};
+
+use embassy_sync::blocking_mutex::{Mutex, raw::CriticalSectionRawMutex};
+use embassy_time::Instant;
+use esp_radio::wifi::WifiStaState;
+use serde::Serialize;
+
+use crate::{
+    network::auth::AuthError,
+    packages::package_store,
+    probes::{
+        coverage::{self, ChannelStats},
+        filter::{self, FilterStats},
+        pipeline,
+    },
+};
+
+pub const FIRMWARE_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum UploadResult {
+    Never,
+    Success,
+    Failed,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct LastUpload {
+    result: UploadResult,
+    sent_chunks: u32,
+    at: Instant,
+}
+
+static LAST_UPLOAD: Mutex<CriticalSectionRawMutex, Cell<Option<LastUpload>>> =
+    Mutex::new(Cell::new(None));
+
+static WIFI_NETWORK: Mutex<CriticalSectionRawMutex, RefCell<Option<String>>> =
+    Mutex::new(RefCell::new(None));
+
+static WIFI_FALLBACKS: Mutex<CriticalSectionRawMutex, Cell<u32>> = Mutex::new(Cell::new(0));
+
+static UPLOADS_HELD: Mutex<CriticalSectionRawMutex, Cell<Option<AuthError>>> =
+    Mutex::new(Cell::new(None));
+
+/// Called by the uploader at the end of every upload cycle.
+pub fn record_upload(ok: bool, sent_chunks: u32) {
+    let result = if ok {
+        UploadResult::Success
+    } else {
+        UploadResult::Failed
+    };
+    LAST_UPLOAD.lock(|l| {
+        l.set(Some(LastUpload {
+            result,
+            sent_chunks,
+            at: Instant::now(),
+        }))
+    });
+}
+
+/// Why the uploader keeps its packages instead of sending them, `None` once requests can be signed.
+pub fn set_uploads_held(reason: Option<AuthError>) {
+    UPLOADS_HELD.lock(|h| h.set(reason));
+}
+
+/// Network the station is configured for, set whenever one is selected.
+pub fn set_wifi_network(ssid: Option<&str>) {
+    WIFI_NETWORK.lock(|n| n.replace(ssid.map(String::from)));
+}
+
+/// Counts switches to a lower ranked network after repeated connect failures.
+pub fn record_wifi_fallback() {
+    WIFI_FALLBACKS.lock(|f| f.set(f.get().saturating_add(1)));
+}
+
+/// # Node Status
+///
+/// Health summary for maintenance clients, serialized as JSON.
+#[derive(Serialize, Debug)]
+pub struct NodeStatus {
+    pub connected: bool,
+    pub wifi_network: Option<String>,
+    pub wifi_fallbacks: u32,
+    pub last_upload: UploadResult,
+    pub last_upload_age_secs: Option<u64>,
+    pub last_upload_chunks: u32,
+    pub uploads_held: Option<AuthError>,
+    pub buffered_packages: usize,
+    pub firmware: &'static str,
+    pub channels: ChannelStats,
+    pub probe_queue_drops: u32,
+    pub rx_errors: u32,
+    pub mac_filter: FilterStats,
+}
+
+pub fn snapshot() -> NodeStatus {
+    let last = LAST_UPLOAD.lock(|l| l.get());
+    NodeStatus {
+        connected: matches!(esp_radio::wifi::sta_state(), WifiStaState::Connected),
+        wifi_network: WIFI_NETWORK.lock(|n| n.borrow().clone()),
+        wifi_fallbacks: WIFI_FALLBACKS.lock(|f| f.get()),
+        last_upload: last.map_or(UploadResult::Never, |l| l.result),
+        last_upload_age_secs: last.map(|l| l.at.elapsed().as_secs()),
+        last_upload_chunks: last.map_or(0, |l| l.sent_chunks),
+        uploads_held: UPLOADS_HELD.lock(|h| h.get()),
+        buffered_packages: package_store::len(),
+        firmware: FIRMWARE_VERSION,
+        channels: coverage::channel_stats(),
+        probe_queue_drops: pipeline::queue_drops(),
+        rx_errors: pipeline::rx_errors(),
+        mac_filter: filter::stats(),
+    }
+}
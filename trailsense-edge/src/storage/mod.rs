@@ -0,0 +1,95 @@
+use core::cell::RefCell;
+use embassy_sync::blocking_mutex::{Mutex, raw::CriticalSectionRawMutex};
+use embedded_storage::nor_flash::{
+    ErrorType, NorFlash, NorFlashError, NorFlashErrorKind, ReadNorFlash, check_erase, check_read,
+    check_write,
+};
+use esp_bootloader_esp_idf::partitions;
+use esp_storage::FlashStorage;
+use log::error;
+
+static FLASH: Mutex<CriticalSectionRawMutex, RefCell<Option<FlashStorage<'static>>>> =
+    Mutex::new(RefCell::new(None));
+
+pub fn init(flash: FlashStorage<'static>) {
+    FLASH.lock(|f| f.replace(Some(flash)));
+}
+
+/// A data partition from the partition table, addressed relative to its start.
+/// All partitions share the single flash driver, so several of them can be used at the same time.
+pub struct FlashPartition {
+    offset: u32,
+    size: u32,
+}
+
+pub fn find_partition(label: &str) -> Option<FlashPartition> {
+    with_driver(|flash| {
+        let mut table_buffer = [0u8; partitions::PARTITION_TABLE_MAX_LEN];
+        let table = match partitions::read_partition_table(flash, &mut table_buffer) {
+            Ok(t) => t,
+            Err(e) => {
+                error!("Failed to read partition table: {:?}", e);
+                return None;
+            }
+        };
+
+        table
+            .iter()
+            .find(|p| p.label_as_str() == label)
+            .map(|p| FlashPartition {
+                offset: p.offset(),
+                size: p.len(),
+            })
+    })
+    .flatten()
+}
+
+/// Runs `op` with the driver taken out of `FLASH`, so an erase does not keep interrupts disabled
+/// for its whole duration. Flash is only accessed from tasks and `op` never yields, so no other user
+/// can find the driver missing.
+fn with_driver<R>(op: impl FnOnce(&mut FlashStorage<'static>) -> R) -> Option<R> {
+    let mut flash = FLASH.lock(|f| f.borrow_mut().take())?;
+    let result = op(&mut flash);
+    FLASH.lock(|f| f.replace(Some(flash)));
+    Some(result)
+}
+
+fn with_flash<R>(
+    op: impl FnOnce(&mut FlashStorage<'static>) -> Result<R, esp_storage::FlashStorageError>,
+) -> Result<R, NorFlashErrorKind> {
+    with_driver(op)
+        .ok_or(NorFlashErrorKind::Other)?
+        .map_err(|e| e.kind())
+}
+
+impl ErrorType for FlashPartition {
+    type Error = NorFlashErrorKind;
+}
+
+impl ReadNorFlash for FlashPartition {
+    const READ_SIZE: usize = <FlashStorage<'static> as ReadNorFlash>::READ_SIZE;
+
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        check_read(self, offset, bytes.len())?;
+        with_flash(|flash| flash.read(self.offset + offset, bytes))
+    }
+
+    fn capacity(&self) -> usize {
+        self.size as usize
+    }
+}
+
+impl NorFlash for FlashPartition {
+    const WRITE_SIZE: usize = <FlashStorage<'static> as NorFlash>::WRITE_SIZE;
+    const ERASE_SIZE: usize = <FlashStorage<'static> as NorFlash>::ERASE_SIZE;
+
+    fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+        check_erase(self, from, to)?;
+        with_flash(|flash| flash.erase(self.offset + from, self.offset + to))
+    }
+
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        check_write(self, offset, bytes.len())?;
+        with_flash(|flash| flash.write(self.offset + offset, bytes))
+    }
+}
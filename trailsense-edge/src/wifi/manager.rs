@@ -1,14 +1,64 @@
-use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use core::cell::{Cell, RefCell};
+
+use embassy_sync::blocking_mutex::{Mutex, raw::CriticalSectionRawMutex};
 use embassy_sync::channel::Receiver;
+use embassy_time::{Duration, Instant, WithTimeout};
+use heapless::Vec as HeaplessVec;
 
 use esp_radio::wifi::{PromiscuousPkt, Sniffer};
-use log::{error, info};
+use log::{error, info, warn};
+
+use crate::{config::MAX_HOP_CHANNELS, probes::coverage};
+
+const STATS_LOG_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// # Channel Driver
+///
+/// The only place that calls into the Wi-Fi driver directly, esp-radio has no safe wrapper for channel changes.
+/// esp-radio 0.17 links the ESP-IDF Wi-Fi blobs, which export these as declared in `esp_wifi.h`:
+/// `esp_err_t esp_wifi_set_channel(uint8_t primary, wifi_second_chan_t second)` and
+/// `esp_err_t esp_wifi_get_channel(uint8_t *primary, wifi_second_chan_t *second)`, `esp_err_t` being an
+/// `int32_t` and `wifi_second_chan_t` a C enum, 4 bytes on Xtensa. Recheck both when bumping esp-radio.
+mod driver {
+    /// `WIFI_SECOND_CHAN_NONE`, hopping stays on 20 MHz channels.
+    const SECOND_CHANNEL_NONE: u32 = 0;
+
+    unsafe extern "C" {
+        fn esp_wifi_set_channel(primary: u8, second: u32) -> i32;
+        fn esp_wifi_get_channel(primary: *mut u8, second: *mut u32) -> i32;
+    }
+
+    /// Returns the driver's error code on failure, e.g. while Wi-Fi is not started.
+    pub fn set_channel(channel: u8) -> Result<(), i32> {
+        // SAFETY: takes plain values, the driver checks the channel and its own state.
+        let err = unsafe { esp_wifi_set_channel(channel, SECOND_CHANNEL_NONE) };
+        if err == 0 { Ok(()) } else { Err(err) }
+    }
+
+    pub fn channel() -> Option<u8> {
+        let mut primary = 0u8;
+        let mut second = 0u32;
+        // SAFETY: both pointers are valid and correctly sized for the duration of the call.
+        let err = unsafe { esp_wifi_get_channel(&mut primary, &mut second) };
+        (err == 0 && primary != 0).then_some(primary)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ChannelPlan {
+    /// Stay on whatever channel the station or portal uses.
+    Fixed,
+    /// Cycle through `Config::hop_channels`, `Config::hop_dwell_ms` on each. Paused while the station
+    /// connects or is associated, see `Config::channel_hopping`.
+    Hopping,
+}
 
 #[derive(PartialEq)]
 pub enum WifiCmd {
     StartSniffing,
     StopSniffing,
     EnableSta,
+    SetChannelPlan(ChannelPlan),
 }
 
 // pub enum WifiEvt { Currently unused
@@ -16,27 +66,179 @@ pub enum WifiCmd {
 //     Sniffing,
 // }
 
+#[derive(Clone, Copy)]
+struct RadioState {
+    claims: u8,
+    /// Channel the radio was on before hopping moved it away, `None` while it is there.
+    home: Option<u8>,
+}
+
+static RADIO: Mutex<CriticalSectionRawMutex, Cell<RadioState>> =
+    Mutex::new(Cell::new(RadioState {
+        claims: 0,
+        home: None,
+    }));
+
+struct HopSettings {
+    channels: HeaplessVec<u8, MAX_HOP_CHANNELS>,
+    dwell: Duration,
+}
+
+/// Set by `config::apply`, so hopping does not clone the whole config every dwell.
+static HOP: Mutex<CriticalSectionRawMutex, RefCell<HopSettings>> =
+    Mutex::new(RefCell::new(HopSettings {
+        channels: HeaplessVec::new(),
+        dwell: Duration::from_millis(250),
+    }));
+
+/// Channels beyond `MAX_HOP_CHANNELS` are ignored, `Config::validate` rejects longer lists.
+pub fn configure_hopping(channels: &[u8], dwell: Duration) {
+    HOP.lock(|h| {
+        let mut hop = h.borrow_mut();
+        hop.channels.clear();
+        for &channel in channels.iter().take(MAX_HOP_CHANNELS) {
+            let _ = hop.channels.push(channel);
+        }
+        hop.dwell = dwell;
+    });
+}
+
+fn hop_dwell() -> Duration {
+    HOP.lock(|h| h.borrow().dwell)
+}
+
+/// Advances `index` through the hop list, `None` while it is empty.
+fn next_hop(index: &mut usize) -> Option<u8> {
+    HOP.lock(|h| {
+        let channels = &h.borrow().channels;
+        if channels.is_empty() {
+            return None;
+        }
+        *index = (*index + 1) % channels.len();
+        Some(channels[*index])
+    })
+}
+
+/// # Radio Claim
+///
+/// Held while the station or portal needs the radio on its own channel. Claiming moves a hopping
+/// radio back right away and hopping waits until every claim is dropped.
+pub struct RadioClaim(());
+
+pub fn claim_radio() -> RadioClaim {
+    let home = RADIO.lock(|r| {
+        let mut state = r.get();
+        state.claims = state.claims.saturating_add(1);
+        let home = state.home.take();
+        r.set(state);
+        home
+    });
+    if let Some(home) = home {
+        set_channel(home);
+    }
+    RadioClaim(())
+}
+
+impl Drop for RadioClaim {
+    fn drop(&mut self) {
+        RADIO.lock(|r| {
+            let mut state = r.get();
+            state.claims = state.claims.saturating_sub(1);
+            r.set(state);
+        });
+    }
+}
+
+/// Moves to `channel` unless the radio is claimed.
+fn hop_to(channel: u8) {
+    let state = RADIO.lock(|r| r.get());
+    if state.claims > 0 {
+        return;
+    }
+    if state.home.is_none() {
+        let home = driver::channel();
+        RADIO.lock(|r| r.set(RadioState { home, ..r.get() }));
+    }
+    set_channel(channel);
+}
+
+/// Returns to the channel the radio was on before hopping.
+fn park() {
+    let home = RADIO.lock(|r| {
+        let mut state = r.get();
+        let home = state.home.take();
+        r.set(state);
+        home
+    });
+    if let Some(home) = home {
+        set_channel(home);
+    }
+}
+
+fn set_channel(channel: u8) {
+    if let Err(err) = driver::set_channel(channel) {
+        warn!("Failed to set Wi-Fi channel {}: error {}", channel, err);
+    }
+}
+
 #[embassy_executor::task]
 pub async fn wifi_manager_task(
     mut sniffer: Sniffer<'static>,
     callback: fn(PromiscuousPkt),
     receiver: Receiver<'static, CriticalSectionRawMutex, WifiCmd, 4>,
 ) {
+    let mut sniffing = false;
+    let mut plan = ChannelPlan::Fixed;
+    let mut hop_index = 0;
+    let mut last_stats_log = Instant::now();
+
     loop {
-        let cmd = receiver.receive().await;
-        if cmd == WifiCmd::StartSniffing {
-            match sniffer.set_promiscuous_mode(true) {
+        let cmd = if sniffing && plan == ChannelPlan::Hopping {
+            match receiver.receive().with_timeout(hop_dwell()).await {
+                Ok(cmd) => cmd,
+                Err(_) => {
+                    if let Some(channel) = next_hop(&mut hop_index) {
+                        hop_to(channel);
+                    }
+                    if last_stats_log.elapsed() >= STATS_LOG_INTERVAL {
+                        coverage::log_channel_stats();
+                        last_stats_log = Instant::now();
+                    }
+                    continue;
+                }
+            }
+        } else {
+            receiver.receive().await
+        };
+
+        match cmd {
+            WifiCmd::StartSniffing => match sniffer.set_promiscuous_mode(true) {
                 Ok(()) => {
                     info!("Enabled Promiscuous Mode");
                     sniffer.set_receive_cb(callback);
+                    coverage::set_sniffing(true);
+                    sniffing = true;
                 }
                 Err(e) => error!("Failed to enable promiscuous mode: {:?}", e),
-            }
-        } else if cmd == WifiCmd::StopSniffing {
-            match sniffer.set_promiscuous_mode(false) {
-                Ok(()) => info!("Disabled Promiscuous mode"),
+            },
+            WifiCmd::StopSniffing => match sniffer.set_promiscuous_mode(false) {
+                Ok(()) => {
+                    info!("Disabled Promiscuous mode");
+                    coverage::set_sniffing(false);
+                    sniffing = false;
+                    park();
+                }
                 Err(e) => error!("Failed to disable promiscuous mode: {:?}", e),
+            },
+            WifiCmd::SetChannelPlan(new_plan) if new_plan != plan => {
+                info!("Sniffer channel plan {:?}", new_plan);
+                coverage::log_channel_stats();
+                if new_plan == ChannelPlan::Fixed {
+                    park();
+                }
+                plan = new_plan;
             }
+            WifiCmd::SetChannelPlan(_) | WifiCmd::EnableSta => {}
         }
     }
 }
@@ -1,10 +1,13 @@
-use embassy_net::{DhcpConfig, Stack, StackResources};
+use embassy_net::{DhcpConfig, Ipv4Cidr, Stack, StackResources, StaticConfigV4};
 use embassy_time::{Duration, Timer};
 use esp_hal::rng::Rng;
 use esp_radio::wifi::WifiDevice;
 use log::info;
 
+pub use trailsense_core::wifi::selection;
+
 pub mod manager;
+pub mod portal;
 pub mod tasks;
 
 // Static helper from tutorial
@@ -17,7 +20,6 @@ macro_rules! mk_static {
 }
 pub struct WifiCtx {
     pub stack: Stack<'static>,
-    pub tls_seed: u64,
 }
 
 pub fn init_stack(
@@ -25,7 +27,6 @@ pub fn init_stack(
     wifi_device: WifiDevice<'static>,
 ) -> (WifiCtx, embassy_net::Runner<'static, WifiDevice<'static>>) {
     let net_seed = rng.random() as u64 | ((rng.random() as u64) << 32);
-    let tls_seed = rng.random() as u64 | ((rng.random() as u64) << 32);
 
     let dhcp_config = DhcpConfig::default();
     let config = embassy_net::Config::dhcpv4(dhcp_config);
@@ -33,11 +34,36 @@ pub fn init_stack(
     let (stack, runner) = embassy_net::new(
         wifi_device,
         config,
-        mk_static!(StackResources<3>, StackResources::<3>::new()),
+        mk_static!(StackResources<4>, StackResources::<4>::new()),
         net_seed,
     );
 
-    (WifiCtx { stack, tls_seed }, runner)
+    (WifiCtx { stack }, runner)
+}
+
+/// Stack of the provisioning access point, the node is the gateway at `portal::PORTAL_IP`.
+pub fn init_ap_stack(
+    rng: &mut Rng,
+    wifi_device: WifiDevice<'static>,
+) -> (
+    Stack<'static>,
+    embassy_net::Runner<'static, WifiDevice<'static>>,
+) {
+    let seed = rng.random() as u64 | ((rng.random() as u64) << 32);
+
+    let config = embassy_net::Config::ipv4_static(StaticConfigV4 {
+        address: Ipv4Cidr::new(portal::PORTAL_IP, 24),
+        gateway: Some(portal::PORTAL_IP),
+        dns_servers: Default::default(),
+    });
+
+    embassy_net::new(
+        wifi_device,
+        config,
+        // DHCP server, captive DNS and the HTTP server.
+        mk_static!(StackResources<3>, StackResources::<3>::new()),
+        seed,
+    )
 }
 
 pub async fn wait_for_connection(stack: Stack<'_>) {
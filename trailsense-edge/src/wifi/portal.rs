@@ -0,0 +1,326 @@
+extern crate alloc;
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+
+use edge_dhcp::{
+    io::{self, DEFAULT_SERVER_PORT},
+    server::{Server, ServerOptions},
+};
+use edge_nal::UdpBind;
+use edge_nal_embassy::{Udp, UdpBuffers};
+use embassy_net::{
+    Stack,
+    tcp::TcpSocket,
+    udp::{PacketMetadata, UdpSocket},
+};
+use embassy_time::{Duration, Timer};
+use esp_radio::wifi::{AccessPointConfig, AuthMethod};
+use log::{error, info, warn};
+
+use crate::config::{self, Config, MAX_WIFI_NETWORKS, WifiNetwork};
+
+pub use trailsense_core::wifi::portal::*;
+
+pub const PORTAL_IP: Ipv4Addr = Ipv4Addr::new(192, 168, 4, 1);
+
+const HTTP_PORT: u16 = 80;
+const DNS_PORT: u16 = 53;
+const MAX_REQUEST_LEN: usize = 2048;
+const SOCKET_TIMEOUT: Duration = Duration::from_secs(10);
+const SERVER_RETRY_DELAY: Duration = Duration::from_millis(500);
+const RESTART_DELAY: Duration = Duration::from_secs(1);
+
+/// Access point shown to the phone, named after `Config::node_name` and secured with `Config::portal_password`.
+pub fn access_point_config() -> AccessPointConfig {
+    let config = config::get();
+    let ssid = config.node_name();
+    // Never open, the portal hands out the Wi-Fi credentials and accepts a new API URL.
+    AccessPointConfig::default()
+        .with_ssid(ssid.as_str().into())
+        .with_auth_method(AuthMethod::Wpa2Personal)
+        .with_password(config.portal_password.as_str().into())
+}
+
+/// Hands out addresses on the portal network with the portal as gateway and DNS server.
+#[embassy_executor::task]
+pub async fn dhcp_task(stack: Stack<'static>) {
+    let mut buf = [0u8; 1500];
+    let mut gw_buf = [Ipv4Addr::UNSPECIFIED];
+    let dns = [PORTAL_IP];
+    let buffers = UdpBuffers::<2, 1024, 1024, 4>::new();
+    let udp = Udp::new(stack, &buffers);
+
+    loop {
+        stack.wait_link_up().await;
+        let mut socket = match udp
+            .bind(SocketAddr::V4(SocketAddrV4::new(
+                Ipv4Addr::UNSPECIFIED,
+                DEFAULT_SERVER_PORT,
+            )))
+            .await
+        {
+            Ok(s) => s,
+            Err(e) => {
+                error!("Failed to bind DHCP server socket: {:?}", e);
+                Timer::after(SERVER_RETRY_DELAY).await;
+                continue;
+            }
+        };
+
+        let mut options = ServerOptions::new(PORTAL_IP, Some(&mut gw_buf));
+        options.dns = &dns;
+        if let Err(e) = io::server::run(
+            &mut Server::<_, 8>::new_with_et(PORTAL_IP),
+            &options,
+            &mut socket,
+            &mut buf,
+        )
+        .await
+        {
+            warn!("DHCP server error: {:?}", e);
+        }
+        Timer::after(SERVER_RETRY_DELAY).await;
+    }
+}
+
+/// Answers every DNS query with the portal address, which makes phones open the captive portal page.
+#[embassy_executor::task]
+pub async fn dns_task(stack: Stack<'static>) {
+    let mut rx_meta = [PacketMetadata::EMPTY; 2];
+    let mut rx_buffer = [0u8; 512];
+    let mut tx_meta = [PacketMetadata::EMPTY; 2];
+    let mut tx_buffer = [0u8; 512];
+    let mut socket = UdpSocket::new(
+        stack,
+        &mut rx_meta,
+        &mut rx_buffer,
+        &mut tx_meta,
+        &mut tx_buffer,
+    );
+    if let Err(e) = socket.bind(DNS_PORT) {
+        error!("Failed to bind portal DNS socket: {:?}", e);
+        return;
+    }
+
+    let mut query = [0u8; 256];
+    loop {
+        let (len, meta) = match socket.recv_from(&mut query).await {
+            Ok(v) => v,
+            Err(e) => {
+                warn!("Portal DNS receive failed: {:?}", e);
+                continue;
+            }
+        };
+        if let Some(answer) = dns_answer(&query[..len], PORTAL_IP) {
+            if let Err(e) = socket.send_to(&answer, meta.endpoint).await {
+                warn!("Portal DNS send failed: {:?}", e);
+            }
+        }
+    }
+}
+
+/// # HTTP Task
+///
+/// Serves the provisioning form for every GET, so the OS connectivity checks land on it as well,
+/// and stores the submitted settings on `POST /save` before restarting into station mode.
+#[embassy_executor::task]
+pub async fn http_task(stack: Stack<'static>) {
+    let mut rx_buffer = [0u8; 1024];
+    let mut tx_buffer = [0u8; 2048];
+
+    loop {
+        let mut socket = TcpSocket::new(stack, &mut rx_buffer, &mut tx_buffer);
+        socket.set_timeout(Some(SOCKET_TIMEOUT));
+        if let Err(e) = socket.accept(HTTP_PORT).await {
+            warn!("Portal accept failed: {:?}", e);
+            Timer::after(SERVER_RETRY_DELAY).await;
+            continue;
+        }
+
+        let mut request = Vec::new();
+        let mut chunk = [0u8; 256];
+        while !request_complete(&request) && request.len() < MAX_REQUEST_LEN {
+            match socket.read(&mut chunk).await {
+                Ok(0) | Err(_) => break,
+                Ok(n) => request.extend_from_slice(&chunk[..n]),
+            }
+        }
+
+        // A request cut off by the size cap or a closed connection is never handled, a partial body
+        // would otherwise save a config with fields missing.
+        let (response, saved) = if request_complete(&request) {
+            handle_request(&request)
+        } else if request.len() >= MAX_REQUEST_LEN {
+            warn!("Portal request over {} bytes rejected", MAX_REQUEST_LEN);
+            (
+                response("413 Payload Too Large", "<p>Request too large</p>"),
+                false,
+            )
+        } else {
+            warn!("Incomplete portal request rejected");
+            (
+                response("400 Bad Request", "<p>Incomplete request</p>"),
+                false,
+            )
+        };
+        let mut written = 0;
+        while written < response.len() {
+            match socket.write(&response[written..]).await {
+                Ok(0) | Err(_) => break,
+                Ok(n) => written += n,
+            }
+        }
+        let _ = socket.flush().await;
+        socket.close();
+
+        if saved {
+            info!("Provisioning saved, restarting into station mode");
+            Timer::after(RESTART_DELAY).await;
+            esp_hal::system::software_reset();
+        }
+    }
+}
+
+/// Returns the raw HTTP response and whether a new configuration was stored.
+pub fn handle_request(request: &[u8]) -> (Vec<u8>, bool) {
+    let head_end = find(request, b"\r\n\r\n").unwrap_or(request.len());
+    let head = core::str::from_utf8(&request[..head_end]).unwrap_or("");
+    let body = request.get(head_end + 4..).unwrap_or(&[]);
+    let mut request_line = head.lines().next().unwrap_or("").split(' ');
+    let method = request_line.next().unwrap_or("");
+    let path = request_line.next().unwrap_or("");
+
+    match (method, path) {
+        ("POST", "/save") => {
+            let current = config::get();
+            let mut config = Config::clone(&current);
+            let form = parse_form(body);
+            let field = |name: &str| {
+                form.iter()
+                    .find(|(key, _)| key == name)
+                    .map(|(_, value)| value.as_str())
+            };
+
+            // Rows are in priority order, an empty SSID drops the row.
+            config.wifi_networks.clear();
+            for index in 0..MAX_WIFI_NETWORKS {
+                let ssid = field(&format!("ssid{}", index)).unwrap_or("");
+                if ssid.is_empty() {
+                    continue;
+                }
+                // An empty password keeps the stored one, so reordering or changing only the URL does not need it.
+                let password = match field(&format!("password{}", index)) {
+                    Some(password) if !password.is_empty() => password.to_string(),
+                    _ => current
+                        .wifi_networks
+                        .iter()
+                        .find(|n| n.ssid == ssid)
+                        .map(|n| n.password.clone())
+                        .unwrap_or_default(),
+                };
+                config.wifi_networks.push(WifiNetwork {
+                    ssid: ssid.to_string(),
+                    password,
+                });
+            }
+            if let Some(api_url) = field("api_url") {
+                config.api_url = api_url.to_string();
+            }
+
+            if config.wifi_networks.is_empty() {
+                return (
+                    page(&current, Some("At least one Wi-Fi network is required")),
+                    false,
+                );
+            }
+            match config::update(config.clone()) {
+                Ok(()) => (
+                    response(
+                        "200 OK",
+                        "<p>Saved. The node restarts and joins the network.</p>",
+                    ),
+                    true,
+                ),
+                Err(e) => {
+                    warn!("Rejected provisioning submission: {:?}", e);
+                    (page(&config, Some(&format!("{:?}", e))), false)
+                }
+            }
+        }
+        ("GET", _) => (page(&config::get(), None), false),
+        _ => (
+            response("405 Method Not Allowed", "<p>Not supported</p>"),
+            false,
+        ),
+    }
+}
+
+fn page(config: &Config, error: Option<&str>) -> Vec<u8> {
+    let error = error
+        .map(|e| format!("<p style=\"color:red\">{}</p>", escape_html(e)))
+        .unwrap_or_default();
+    let mut networks = String::new();
+    for index in 0..MAX_WIFI_NETWORKS {
+        let ssid = config
+            .wifi_networks
+            .get(index)
+            .map_or("", |n| n.ssid.as_str());
+        networks.push_str(&format!(
+            "<fieldset><legend>Wi-Fi network {}{}</legend>\
+             <p><label>SSID (empty removes it)<br><input name=\"ssid{}\" maxlength=\"32\" value=\"{}\"></label></p>\
+             <p><label>Password (empty keeps the current one)<br><input name=\"password{}\" type=\"password\" maxlength=\"64\"></label></p>\
+             </fieldset>",
+            index + 1,
+            if index == 0 { " (preferred)" } else { "" },
+            index,
+            escape_html(ssid),
+            index,
+        ));
+    }
+    let body = format!(
+        "<h1>Trailsense node {}</h1>{}\
+         <form method=\"post\" action=\"/save\">{}\
+         <p><label>API URL<br><input name=\"api_url\" value=\"{}\"></label></p>\
+         <p><button type=\"submit\">Save and restart</button></p></form>",
+        escape_html(&config.device_id),
+        error,
+        networks,
+        escape_html(&config.api_url),
+    );
+    response("200 OK", &body)
+}
+
+fn response(status: &str, body: &str) -> Vec<u8> {
+    let html = format!(
+        "<!DOCTYPE html><html><head><meta name=\"viewport\" content=\"width=device-width\">\
+         <title>Trailsense setup</title></head><body>{}</body></html>",
+        body
+    );
+    format!(
+        "HTTP/1.1 {}\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\n\
+         Cache-Control: no-store\r\nConnection: close\r\n\r\n{}",
+        status,
+        html.len(),
+        html
+    )
+    .into_bytes()
+}
+
+fn escape_html(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
@@ -1,11 +1,21 @@
+extern crate alloc;
+use alloc::vec::Vec;
+
 use embassy_net::Runner;
 use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, channel::Receiver};
-use embassy_time::{Duration, Timer};
-use esp_radio::wifi::{ClientConfig, ModeConfig, WifiController, WifiDevice, WifiStaState};
-use log::{error, info};
+use embassy_time::{Duration, Timer, WithTimeout};
+use esp_radio::wifi::{
+    ClientConfig, ModeConfig, ScanConfig, WifiController, WifiDevice, WifiError, WifiStaState,
+};
+use log::{error, info, warn};
+
+use crate::{
+    config::{self, WifiNetwork},
+    probes::coverage,
+    status,
+    wifi::{manager, portal, selection::NetworkSelector},
+};
 
-const SSID: Option<&'static str> = option_env!("WIFI_SSID");
-const PASSWORD: Option<&'static str> = option_env!("WIFI_PASSWORD");
 const WIFI_RETRY_DELAY: Duration = Duration::from_secs(5);
 const WIFI_POLL_INTERVAL: Duration = Duration::from_millis(500);
 const RECONNECT_SETTLE_DELAY: Duration = Duration::from_secs(2);
@@ -17,7 +27,7 @@ pub enum WifiControlCmd {
     RestartController,
 }
 
-#[embassy_executor::task]
+#[embassy_executor::task(pool_size = 2)]
 pub async fn net_task(mut runner: Runner<'static, WifiDevice<'static>>) {
     runner.run().await
 }
@@ -27,21 +37,20 @@ pub async fn connect(
     mut controller: WifiController<'static>,
     control_receiver: Receiver<'static, CriticalSectionRawMutex, WifiControlCmd, 4>,
 ) {
-    let ssid = match SSID {
-        Some(v) => v,
-        None => {
-            error!("WIFI_SSID not set");
-            return;
-        }
-    };
+    // Connecting, scanning, the association and the portal need the radio on their channel,
+    // hopping only runs while the station waits to retry a failed connect.
+    let mut radio_claim = Some(manager::claim_radio());
 
-    let password = match PASSWORD {
-        Some(v) => v,
-        None => {
-            error!("WIFI_PASSWORD not set");
-            return;
-        }
-    };
+    // Only a saved submission or a reconnect after provisioning over BLE leaves the portal in this case.
+    while config::get().wifi_networks.is_empty() {
+        warn!("No Wi-Fi network configured, opening provisioning portal");
+        run_portal(&mut controller, &control_receiver, None).await;
+        Timer::after(WIFI_RETRY_DELAY).await;
+    }
+
+    let mut config = config::get();
+    let mut selector = NetworkSelector::new();
+    let mut consecutive_failures: u8 = 0;
 
     info!("Connecting to wifi");
 
@@ -52,32 +61,41 @@ pub async fn connect(
                 if let Err(e) = controller.disconnect_async().await {
                     error!("Failed to disconnect Wi-Fi during reconnect: {:?}", e);
                 }
+                // Networks are only selected when the controller starts, so changed ones need a stop.
+                let latest = config::get();
+                if latest.wifi_networks != config.wifi_networks {
+                    info!("Wi-Fi networks changed, restarting controller");
+                    if let Err(e) = stop_controller(&mut controller).await {
+                        error!("Failed to stop Wi-Fi controller: {:?}", e);
+                    }
+                    selector = NetworkSelector::new();
+                }
+                config = latest;
                 Timer::after(RECONNECT_SETTLE_DELAY).await;
             } else if cmd == WifiControlCmd::RestartController {
                 info!("Wi-Fi controller restart requested");
                 if let Err(e) = controller.disconnect_async().await {
                     error!("Failed to disconnect Wi-Fi before restart: {:?}", e);
                 }
-                if let Err(e) = controller.stop_async().await {
+                if let Err(e) = stop_controller(&mut controller).await {
                     error!("Failed to stop Wi-Fi controller: {:?}", e);
                 }
                 Timer::after(RESTART_SETTLE_DELAY).await;
             }
         }
 
+        if radio_claim.is_none() {
+            radio_claim = Some(manager::claim_radio());
+        }
         if matches!(esp_radio::wifi::sta_state(), WifiStaState::Connected) {
             Timer::after(WIFI_POLL_INTERVAL).await;
             continue;
         }
 
         if !matches!(controller.is_started(), Ok(true)) {
-            let client_config = ModeConfig::Client(
-                ClientConfig::default()
-                    .with_ssid(ssid.into())
-                    .with_password(password.into()),
-            );
-
-            if let Err(e) = controller.set_config(&client_config) {
+            // Scanning needs a started station, the network is configured once one is chosen.
+            let idle_config = ModeConfig::Client(ClientConfig::default());
+            if let Err(e) = controller.set_config(&idle_config) {
                 error!("Failed to configure wifi client: {:?}", e);
                 Timer::after(WIFI_RETRY_DELAY).await;
                 continue;
@@ -88,14 +106,176 @@ pub async fn connect(
                 Timer::after(WIFI_RETRY_DELAY).await;
                 continue;
             }
+
+            let Some(network) = select_network(&mut controller, &mut selector, &config).await
+            else {
+                warn!("No Wi-Fi network configured, opening provisioning portal");
+                run_portal(&mut controller, &control_receiver, None).await;
+                config = config::get();
+                continue;
+            };
+
+            let client_config = ModeConfig::Client(
+                ClientConfig::default()
+                    .with_ssid(network.ssid.as_str().into())
+                    .with_password(network.password.as_str().into()),
+            );
+            if let Err(e) = controller.set_config(&client_config) {
+                error!("Failed to configure wifi client: {:?}", e);
+                // Stopped again so the next pass selects instead of connecting without a network.
+                let _ = stop_controller(&mut controller).await;
+                Timer::after(WIFI_RETRY_DELAY).await;
+                continue;
+            }
+            status::set_wifi_network(Some(&network.ssid));
+            coverage::set_radio_available(true);
         }
 
         match controller.connect_async().await {
-            Ok(_) => info!("Wifi connected!"),
+            Ok(_) => {
+                info!("Wifi connected to '{}'", selector.current().unwrap_or(""));
+                consecutive_failures = 0;
+                selector.record_success();
+            }
             Err(e) => {
-                error!("Failed to connect to wifi: {:?}", e);
+                error!(
+                    "Failed to connect to wifi '{}': {:?}",
+                    selector.current().unwrap_or(""),
+                    e
+                );
+                consecutive_failures = consecutive_failures.saturating_add(1);
+                if selector.record_failure(config.wifi_failover_after_failures) {
+                    warn!(
+                        "Wi-Fi network failed {} times in a row, selecting the next one",
+                        config.wifi_failover_after_failures
+                    );
+                    status::record_wifi_fallback();
+                    // A stopped controller scans and selects again on the next pass.
+                    if let Err(e) = stop_controller(&mut controller).await {
+                        error!("Failed to stop Wi-Fi controller: {:?}", e);
+                    }
+                }
+                if config.portal_after_failures > 0
+                    && consecutive_failures >= config.portal_after_failures
+                {
+                    warn!(
+                        "Wi-Fi failed {} times in a row, opening provisioning portal",
+                        consecutive_failures
+                    );
+                    run_portal(
+                        &mut controller,
+                        &control_receiver,
+                        Some(config.portal_timeout()),
+                    )
+                    .await;
+                    consecutive_failures = 0;
+                    continue;
+                }
+                radio_claim = None;
                 Timer::after(WIFI_RETRY_DELAY).await;
             }
         }
     }
 }
+
+/// Sniffing is down until the controller is started and configured again.
+async fn stop_controller(controller: &mut WifiController<'static>) -> Result<(), WifiError> {
+    coverage::set_radio_available(false);
+    controller.stop_async().await
+}
+
+/// Scans with the started controller and picks the network to join, `None` when none is configured.
+async fn select_network(
+    controller: &mut WifiController<'static>,
+    selector: &mut NetworkSelector,
+    config: &config::Config,
+) -> Option<WifiNetwork> {
+    let scan = match controller
+        .scan_with_config_async(ScanConfig::default())
+        .await
+    {
+        Ok(scan) => scan,
+        Err(e) => {
+            // Selection still works blind, in priority order.
+            warn!("Wi-Fi scan failed: {:?}", e);
+            Vec::new()
+        }
+    };
+    let visible: Vec<(&str, i8)> = scan
+        .iter()
+        .map(|ap| (ap.ssid.as_str(), ap.signal_strength))
+        .collect();
+
+    let network = selector.select(&config.wifi_networks, &visible)?;
+    let rssi = visible
+        .iter()
+        .filter(|(ssid, _)| *ssid == network.ssid)
+        .map(|(_, rssi)| *rssi)
+        .max();
+    match rssi {
+        Some(rssi) => info!(
+            "Selected Wi-Fi network '{}' ({} dBm, {} access points seen)",
+            network.ssid,
+            rssi,
+            visible.len()
+        ),
+        None => info!(
+            "Selected Wi-Fi network '{}', not seen in scan of {} access points",
+            network.ssid,
+            visible.len()
+        ),
+    }
+    Some(network.clone())
+}
+
+/// Switches the controller to the provisioning access point until `timeout` or the next control command.
+/// The controller is left stopped, so the station loop reconfigures it afterwards.
+async fn run_portal(
+    controller: &mut WifiController<'static>,
+    control_receiver: &Receiver<'static, CriticalSectionRawMutex, WifiControlCmd, 4>,
+    timeout: Option<Duration>,
+) {
+    // The password is all that protects the Wi-Fi credentials the portal hands out.
+    if config::get().portal_password.is_empty() {
+        error!("No portal password and no device secret, provisioning portal stays closed");
+        Timer::after(WIFI_RETRY_DELAY).await;
+        return;
+    }
+
+    if let Err(e) = stop_controller(controller).await {
+        error!("Failed to stop Wi-Fi controller for the portal: {:?}", e);
+    }
+
+    let ap_config = ModeConfig::AccessPoint(portal::access_point_config());
+    if let Err(e) = controller.set_config(&ap_config) {
+        error!("Failed to configure provisioning access point: {:?}", e);
+        return;
+    }
+    if let Err(e) = controller.start_async().await {
+        error!("Failed to start provisioning access point: {:?}", e);
+        return;
+    }
+    info!("Provisioning portal up at http://{}", portal::PORTAL_IP);
+    coverage::set_radio_available(true);
+
+    let closed_by_command = match timeout {
+        Some(timeout) => control_receiver
+            .receive()
+            .with_timeout(timeout)
+            .await
+            .is_ok(),
+        None => {
+            control_receiver.receive().await;
+            true
+        }
+    };
+
+    if closed_by_command {
+        info!("Provisioning portal closed on request, retrying Wi-Fi");
+    } else {
+        info!("Provisioning portal timed out, retrying Wi-Fi");
+    }
+    if let Err(e) = stop_controller(controller).await {
+        error!("Failed to stop provisioning access point: {:?}", e);
+    }
+}